@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail, ensure};
+use borsh::{BorshDeserialize, BorshSerialize};
+use risc0_zkvm::{Digest, Receipt, sha::Digestible};
+use serde::{Deserialize, Serialize};
+
+use crate::{CircuitVersion, ReceiptKind, ShrinkBitvm2ReceiptClaim, VersionRegistry, verify};
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Commit of `risc0-to-bitvm2` the `verify_for_guest` circuit is built from.
+pub const CIRCUIT_COMMIT: &str = "b47483ae7ff2bbfbf6d4448bac76aa26afc80e47";
+
+/// Self-describing BitVM2 proof, serializable with borsh or JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
+pub struct Bitvm2ProofBundle {
+    pub version: u32,
+    /// Groth16 seal, either compressed or uncompressed.
+    #[serde(with = "hex::serde")]
+    pub seal: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub image_id: [u8; 32],
+    #[serde(with = "hex::serde")]
+    pub journal: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub claim_digest: [u8; 32],
+    /// [CircuitVersion::verifier_parameters_digest] of the version the seal was produced with.
+    #[serde(with = "hex::serde")]
+    pub verifier_parameters_digest: [u8; 32],
+    /// Commit of the circuit the seal was produced with.
+    pub circuit_commit: String,
+    /// Digest of the Groth16 verifying key produced by the trusted setup.
+    #[serde(with = "hex::serde")]
+    pub setup_id: [u8; 32],
+}
+
+impl Bitvm2ProofBundle {
+    pub fn new(seal: Vec<u8>, image_id: impl Into<Digest>, journal: Vec<u8>) -> Self {
+        Self::for_version(seal, image_id, journal, &CircuitVersion::current())
+    }
+
+    /// Same as [Bitvm2ProofBundle::new], for a seal produced with the circuit `version`.
+    pub fn for_version(
+        seal: Vec<u8>,
+        image_id: impl Into<Digest>,
+        journal: Vec<u8>,
+        version: &CircuitVersion,
+    ) -> Self {
+        let image_id = image_id.into();
+        let claim_digest =
+            ShrinkBitvm2ReceiptClaim::for_version(image_id, journal.clone(), version).digest();
+        Self {
+            version: BUNDLE_VERSION,
+            seal,
+            image_id: image_id.into(),
+            journal,
+            claim_digest: claim_digest.into(),
+            verifier_parameters_digest: version.verifier_parameters_digest().into(),
+            circuit_commit: version.circuit_commit.clone(),
+            setup_id: version.verifying_key.r0_key().digest().into(),
+        }
+    }
+
+    /// Builds a bundle from a receipt produced by [crate::finalize].
+    pub fn from_receipt(receipt: &Receipt) -> Result<Self> {
+        Self::from_receipt_with_registry(receipt, &VersionRegistry::builtin())
+    }
+
+    /// Same as [Bitvm2ProofBundle::from_receipt], accepting the circuit versions of `registry`.
+    pub fn from_receipt_with_registry(
+        receipt: &Receipt,
+        registry: &VersionRegistry,
+    ) -> Result<Self> {
+        let ReceiptKind::Bitvm2(view) = ReceiptKind::classify_with_registry(receipt, registry)?
+        else {
+            bail!("receipt is not a BitVM2 receipt");
+        };
+        Ok(Self::for_version(
+            view.seal.to_vec(),
+            view.image_id,
+            view.journal.to_vec(),
+            &view.version,
+        ))
+    }
+
+    /// Checks that the bundle is internally consistent and that the seal verifies.
+    pub fn verify(&self) -> Result<()> {
+        self.verify_with_registry(&VersionRegistry::builtin())
+    }
+
+    /// Same as [Bitvm2ProofBundle::verify], accepting the circuit versions of `registry`.
+    pub fn verify_with_registry(&self, registry: &VersionRegistry) -> Result<()> {
+        self.check_version()?;
+        let version = registry.lookup(&Digest::from(self.verifier_parameters_digest))?;
+        ensure!(
+            self.circuit_commit == version.circuit_commit,
+            "unknown circuit commit: {}",
+            self.circuit_commit
+        );
+        ensure!(
+            Digest::from(self.setup_id) == version.verifying_key.r0_key().digest(),
+            "setup id does not match the verifying key"
+        );
+
+        let claim_digest = ShrinkBitvm2ReceiptClaim::for_version(
+            Digest::from(self.image_id),
+            self.journal.clone(),
+            version,
+        )
+        .digest();
+        ensure!(
+            Digest::from(self.claim_digest) == claim_digest,
+            "claim digest does not match image id and journal"
+        );
+
+        verify::verify_integrity_with_key(
+            self.seal.as_slice(),
+            &self.claim_digest,
+            &version.verifying_key,
+        )
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(borsh::to_vec(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let bundle: Self = borsh::from_slice(bytes).context("failed to decode borsh bundle")?;
+        bundle.check_version()?;
+        Ok(bundle)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let bundle: Self = serde_json::from_str(json).context("failed to decode JSON bundle")?;
+        bundle.check_version()?;
+        Ok(bundle)
+    }
+
+    /// Reads a bundle, using JSON for `.json` files and borsh otherwise.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        if is_json(path) {
+            Self::from_json(std::str::from_utf8(&bytes)?)
+        } else {
+            Self::from_bytes(&bytes)
+        }
+    }
+
+    /// Writes a bundle, using JSON for `.json` files and borsh otherwise.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = if is_json(path) {
+            self.to_json()?.into_bytes()
+        } else {
+            self.to_bytes()?
+        };
+        std::fs::write(path, bytes).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn check_version(&self) -> Result<()> {
+        ensure!(
+            self.version == BUNDLE_VERSION,
+            "unsupported bundle version: {}",
+            self.version
+        );
+        Ok(())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle() -> Bitvm2ProofBundle {
+        Bitvm2ProofBundle::new(vec![0u8; 256], [1u32, 2, 3, 4, 5, 6, 7, 8], vec![3u8; 32])
+    }
+
+    #[test]
+    fn borsh_round_trip() {
+        let bundle = bundle();
+        let bytes = bundle.to_bytes().unwrap();
+        assert_eq!(Bitvm2ProofBundle::from_bytes(&bytes).unwrap(), bundle);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let bundle = bundle();
+        let json = bundle.to_json().unwrap();
+        assert_eq!(Bitvm2ProofBundle::from_json(&json).unwrap(), bundle);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = bundle();
+        for name in ["bundle.json", "bundle.bin"] {
+            let path = dir.path().join(name);
+            bundle.write(&path).unwrap();
+            assert_eq!(Bitvm2ProofBundle::read(&path).unwrap(), bundle);
+        }
+    }
+
+    #[test]
+    fn rejects_inconsistent_bundle() {
+        let mut bundle = bundle();
+        bundle.version = BUNDLE_VERSION + 1;
+        assert!(Bitvm2ProofBundle::from_bytes(&bundle.to_bytes().unwrap()).is_err());
+
+        let mut bundle = self::bundle();
+        bundle.journal[0] ^= 1;
+        let err = bundle.verify().unwrap_err();
+        assert!(err.to_string().contains("claim digest"));
+    }
+
+    #[test]
+    fn from_receipt_of_older_version() {
+        use risc0_zkvm::{Groth16Receipt, InnerReceipt, ReceiptClaim};
+
+        let old_version = CircuitVersion {
+            circuit_commit: "0".repeat(40),
+            ..CircuitVersion::with_control(
+                Digest::from([1u32; 8]),
+                CircuitVersion::current().bn254_control_id,
+            )
+        };
+        let mut registry = VersionRegistry::builtin();
+        registry.insert(old_version.clone()).unwrap();
+
+        let image_id = Digest::from([1u32, 2, 3, 4, 5, 6, 7, 8]);
+        let journal = vec![3u8; 32];
+        let inner = Groth16Receipt::new(
+            vec![0u8; 256],
+            ReceiptClaim::ok(image_id, journal.clone()).into(),
+            old_version.verifier_parameters_digest(),
+        );
+        let receipt = Receipt::new(InnerReceipt::Groth16(inner), journal.clone());
+
+        assert!(Bitvm2ProofBundle::from_receipt(&receipt).is_err());
+        let bundle = Bitvm2ProofBundle::from_receipt_with_registry(&receipt, &registry).unwrap();
+        assert_eq!(
+            bundle,
+            Bitvm2ProofBundle::for_version(vec![0u8; 256], image_id, journal, &old_version)
+        );
+        assert_eq!(bundle.circuit_commit, old_version.circuit_commit);
+        assert_ne!(
+            bundle.claim_digest,
+            Bitvm2ProofBundle::new(vec![0u8; 256], image_id, vec![3u8; 32]).claim_digest
+        );
+        let err = bundle.verify().unwrap_err();
+        assert!(err.to_string().contains("unknown"), "{err:?}");
+    }
+
+    #[cfg(feature = "prove")]
+    #[test]
+    fn from_real_proof() {
+        let receipt = crate::test_utils::prove_echo_bitvm2(&[3u8; 32]);
+        let bundle = Bitvm2ProofBundle::from_receipt(&receipt).unwrap();
+        assert_eq!(Digest::from(bundle.image_id), Digest::from(guest::ECHO_ID));
+        bundle.verify().unwrap();
+        Bitvm2ProofBundle::from_bytes(&bundle.to_bytes().unwrap())
+            .unwrap()
+            .verify()
+            .unwrap();
+
+        let mut tampered = bundle.clone();
+        tampered.seal[0] ^= 1;
+        assert!(tampered.verify().is_err());
+    }
+}
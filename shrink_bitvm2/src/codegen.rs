@@ -0,0 +1,71 @@
+//! Renders `src/verify/verifying_key.rs` from a snarkjs `verification_key.json`, see
+//! `cargo xtask codegen-vk`.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use ark_ff::{BigInteger, PrimeField};
+
+use crate::verify::ark_key_from_snarkjs_json;
+
+const HEADER: &str = "\
+// @generated by `cargo xtask codegen-vk` from `verification_key.json`, do not edit.
+//! Groth16 verifying key of the `verify_for_guest` circuit. Field elements are 32 big-endian
+//! bytes and G2 coordinates are `[c0, c1]`.
+";
+
+/// Source of the generated module for the verifying key in `snarkjs_json`.
+pub fn verifying_key_module(snarkjs_json: &str) -> Result<String> {
+    let vk = ark_key_from_snarkjs_json(snarkjs_json)?;
+    let mut out = HEADER.to_string();
+
+    write_g1(&mut out, "ALPHA_G1", &vk.alpha_g1)?;
+    write_g2(&mut out, "BETA_G2", &vk.beta_g2)?;
+    write_g2(&mut out, "GAMMA_G2", &vk.gamma_g2)?;
+    write_g2(&mut out, "DELTA_G2", &vk.delta_g2)?;
+
+    writeln!(
+        out,
+        "\npub const IC: [[[u8; 32]; 2]; {}] = [",
+        vk.gamma_abc_g1.len()
+    )?;
+    for point in &vk.gamma_abc_g1 {
+        writeln!(out, "    [")?;
+        write_fq(&mut out, "        ", &point.x)?;
+        write_fq(&mut out, "        ", &point.y)?;
+        writeln!(out, "    ],")?;
+    }
+    writeln!(out, "];")?;
+    Ok(out)
+}
+
+fn write_g1(out: &mut String, name: &str, point: &ark_bn254::G1Affine) -> Result<()> {
+    writeln!(out, "\npub const {name}: [[u8; 32]; 2] = [")?;
+    write_fq(out, "    ", &point.x)?;
+    write_fq(out, "    ", &point.y)?;
+    writeln!(out, "];")?;
+    Ok(())
+}
+
+fn write_g2(out: &mut String, name: &str, point: &ark_bn254::G2Affine) -> Result<()> {
+    writeln!(out, "\npub const {name}: [[[u8; 32]; 2]; 2] = [")?;
+    for coordinate in [&point.x, &point.y] {
+        writeln!(out, "    [")?;
+        write_fq(out, "        ", &coordinate.c0)?;
+        write_fq(out, "        ", &coordinate.c1)?;
+        writeln!(out, "    ],")?;
+    }
+    writeln!(out, "];")?;
+    Ok(())
+}
+
+fn write_fq(out: &mut String, indent: &str, value: &ark_bn254::Fq) -> Result<()> {
+    let bytes: Vec<String> = value
+        .into_bigint()
+        .to_bytes_be()
+        .iter()
+        .map(|byte| format!("{byte:#04x}"))
+        .collect();
+    writeln!(out, "{indent}[{}],", bytes.join(", "))?;
+    Ok(())
+}
@@ -1,8 +1,13 @@
 use anyhow::Result;
+pub use bundle::Bitvm2ProofBundle;
 pub use receipt_claim::*;
+pub use receipt_kind::{Bitvm2ReceiptView, ReceiptKind};
+pub use registry::{CircuitVersion, VersionRegistry};
 use risc0_circuit_recursion::control_id::BN254_IDENTITY_CONTROL_ID;
 use risc0_groth16::Seal as Groth16Seal;
 use risc0_zkvm::{MaybePruned, Receipt, ReceiptClaim, SuccinctReceipt};
+pub use verifier_parameters::Bitvm2ReceiptVerifierParameters;
+pub use verify::Bitvm2VerifyingKey;
 
 #[cfg(feature = "prove")]
 use {
@@ -11,13 +16,47 @@ use {
     risc0_groth16::ProofJson as Groth16ProofJson,
     risc0_zkvm::sha::Digestible,
     risc0_zkvm::{Digest, Groth16Receipt},
-    std::path::Path,
+    std::{path::Path, time::Instant},
     tempfile::tempdir,
 };
 
+#[cfg(feature = "tokio")]
+pub use tokio_util::sync::CancellationToken;
+
+#[cfg(all(feature = "prove", not(feature = "cuda")))]
+pub use prove::{
+    container::ContainerOptions,
+    docker::RapidsnarkBackend,
+    rapidsnark::{ProcessOutput, RapidsnarkError, RapidsnarkOptions},
+};
+
+pub mod bundle;
+/// Code generation for `cargo xtask codegen-vk`, not part of the public API.
+#[cfg(any(test, feature = "codegen"))]
+#[doc(hidden)]
+pub mod codegen;
+#[cfg(feature = "prove")]
+pub use prove::witgen::{WitnessCache, WitnessGenerator};
+pub use prove::{
+    input::VerifyForGuestInput,
+    wtns::{Symbols, Witness},
+};
 #[cfg(feature = "prove")]
+pub use prover::{Bitvm2ProveInfo, Bitvm2Prover, StageTimings};
+
+#[cfg(feature = "prove")]
+pub mod pipeline;
 mod prove;
+#[cfg(feature = "prove")]
+pub mod prover;
 pub mod receipt_claim;
+pub mod receipt_kind;
+pub mod registry;
+pub mod seal;
+pub mod setup;
+#[cfg(all(test, feature = "prove"))]
+mod test_utils;
+pub mod verifier_parameters;
 pub mod verify;
 
 #[cfg(feature = "prove")]
@@ -25,11 +64,137 @@ pub fn succinct_to_bitvm2(
     succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
     journal: &[u8],
 ) -> Result<Receipt> {
-    let p254_receipt: SuccinctReceipt<ReceiptClaim> =
-        risc0_zkvm::recursion::identity_p254(succinct_receipt).unwrap();
-    let receipt_claim = p254_receipt.claim.clone();
-    let seal = shrink_wrap(&p254_receipt, journal)?;
-    finalize(journal.to_vec(), receipt_claim, &seal.try_into()?)
+    let current = CircuitVersion::current();
+    succinct_to_bitvm2_with_control(
+        succinct_receipt,
+        journal,
+        current.control_root,
+        current.bn254_control_id,
+    )
+}
+
+/// Same as [succinct_to_bitvm2], for a succinct receipt verified against `control_root` and
+/// shrunk with the `identity_p254` program of `bn254_control_id`, instead of the ones of the
+/// risc0 version this crate is built with.
+#[cfg(feature = "prove")]
+pub fn succinct_to_bitvm2_with_control(
+    succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    control_root: Digest,
+    bn254_control_id: Digest,
+) -> Result<Receipt> {
+    let control = CircuitVersion::with_control(control_root, bn254_control_id);
+    run_succinct_to_bitvm2(
+        succinct_receipt,
+        journal,
+        &control,
+        &mut StageTimings::default(),
+    )
+}
+
+#[cfg(feature = "prove")]
+fn run_succinct_to_bitvm2(
+    succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    control: &CircuitVersion,
+    timings: &mut StageTimings,
+) -> Result<Receipt> {
+    let start = Instant::now();
+    let p254_receipt = identity_p254(succinct_receipt, control)?;
+    timings.identity_p254 = start.elapsed();
+
+    let start = Instant::now();
+    let seal = shrink_wrap_with_control(
+        &p254_receipt,
+        journal,
+        control.control_root,
+        control.bn254_control_id,
+    )?;
+    timings.shrink_wrap = start.elapsed();
+
+    let version = CircuitVersion {
+        verifying_key: setup::SetupDir::from_env()?.verifying_key()?,
+        ..control.clone()
+    };
+    finalize_with_version(
+        journal.to_vec(),
+        p254_receipt.claim.clone(),
+        &seal.try_into()?,
+        &version,
+    )
+}
+
+/// Builds the `verify_for_guest` circuit input for a succinct or p254 receipt, for proving with an
+/// external Groth16 prover. Use [finalize_external_proof] to turn the resulting proof into a
+/// BitVM2 receipt.
+#[cfg(feature = "prove")]
+pub fn circuit_input(
+    succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+) -> Result<VerifyForGuestInput> {
+    circuit_input_with_version(succinct_receipt, journal, &CircuitVersion::current())
+}
+
+/// Same as [circuit_input], for proving with the circuit `version`.
+#[cfg(feature = "prove")]
+pub fn circuit_input_with_version(
+    succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    version: &CircuitVersion,
+) -> Result<VerifyForGuestInput> {
+    let p254_receipt = identity_p254(succinct_receipt, version)?;
+    VerifyForGuestInput::for_version(&p254_receipt, journal, version)
+}
+
+/// Runs `identity_p254` on `succinct_receipt` once it is checked against the control root of
+/// `control`. A receipt which already is the output of `identity_p254` is returned as is, the
+/// p254 receipt is checked by [VerifyForGuestInput::for_version] before witness generation.
+#[cfg(feature = "prove")]
+pub(crate) fn identity_p254(
+    succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    control: &CircuitVersion,
+) -> Result<SuccinctReceipt<ReceiptClaim>> {
+    if succinct_receipt.hashfn == registry::P254_HASHFN {
+        return Ok(succinct_receipt.clone());
+    }
+    control.check_succinct_receipt(succinct_receipt)?;
+    risc0_zkvm::recursion::identity_p254(succinct_receipt).context("failed to run identity_p254")
+}
+
+/// Verifies a Groth16 proof of the [circuit_input] of `succinct_receipt` produced by an external
+/// prover, and wraps it into a BitVM2 receipt.
+#[cfg(feature = "prove")]
+pub fn finalize_external_proof(
+    succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    proof_json: Groth16ProofJson,
+) -> Result<Receipt> {
+    finalize_external_proof_with_version(
+        succinct_receipt,
+        journal,
+        proof_json,
+        &CircuitVersion::current(),
+    )
+}
+
+/// Same as [finalize_external_proof], for a proof of the [circuit_input_with_version] of
+/// `version`, e.g. an older registered version or one with an insecure development key.
+#[cfg(feature = "prove")]
+pub fn finalize_external_proof_with_version(
+    succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    proof_json: Groth16ProofJson,
+    version: &CircuitVersion,
+) -> Result<Receipt> {
+    // identity_p254 doesn't change the claim, so the succinct receipt claim can be used as is
+    let image_id = succinct_receipt.claim.as_value()?.pre.digest();
+    let proof_json = verify_shrink_wrap(image_id, journal, proof_json, version)?;
+    finalize_with_version(
+        journal.to_vec(),
+        succinct_receipt.claim.clone(),
+        &proof_json.try_into()?,
+        version,
+    )
 }
 
 #[cfg(feature = "prove")]
@@ -37,34 +202,254 @@ pub fn shrink_wrap(
     p254_receipt: &SuccinctReceipt<ReceiptClaim>,
     journal: &[u8],
 ) -> Result<Groth16ProofJson> {
-    let image_id = p254_receipt.claim.as_value()?.pre.digest();
-    let seal_json = prove::identity_seal_json(journal, p254_receipt)?;
+    #[cfg(feature = "cuda")]
+    {
+        run_shrink_wrap(
+            p254_receipt,
+            journal,
+            &CircuitVersion::current(),
+            prove::cuda::shrink_wrap,
+        )
+    }
+    #[cfg(not(feature = "cuda"))]
+    {
+        shrink_wrap_with_backend(p254_receipt, journal, &RapidsnarkBackend::from_env()?)
+    }
+}
+
+/// Same as [shrink_wrap], with an explicit control root and BN254 control ID, see
+/// [succinct_to_bitvm2_with_control].
+#[cfg(feature = "prove")]
+pub fn shrink_wrap_with_control(
+    p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    control_root: Digest,
+    bn254_control_id: Digest,
+) -> Result<Groth16ProofJson> {
+    let control = CircuitVersion::with_control(control_root, bn254_control_id);
+    #[cfg(feature = "cuda")]
+    {
+        run_shrink_wrap(p254_receipt, journal, &control, prove::cuda::shrink_wrap)
+    }
+    #[cfg(not(feature = "cuda"))]
+    {
+        let backend = RapidsnarkBackend::from_env()?;
+        run_shrink_wrap(
+            p254_receipt,
+            journal,
+            &control,
+            |work_dir, input, claim_digest| {
+                prove::docker::shrink_wrap(work_dir, input, claim_digest, &backend)
+            },
+        )
+    }
+}
+
+/// Same as [shrink_wrap], but with an explicit rapidsnark backend instead of the one
+/// configured in the environment.
+#[cfg(all(feature = "prove", not(feature = "cuda")))]
+pub fn shrink_wrap_with_backend(
+    p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    backend: &RapidsnarkBackend,
+) -> Result<Groth16ProofJson> {
+    run_shrink_wrap(
+        p254_receipt,
+        journal,
+        &CircuitVersion::current(),
+        |work_dir, input, claim_digest| {
+            prove::docker::shrink_wrap(work_dir, input, claim_digest, backend)
+        },
+    )
+}
+
+#[cfg(feature = "prove")]
+fn run_shrink_wrap(
+    p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    control: &CircuitVersion,
+    backend: impl FnOnce(&Path, &VerifyForGuestInput, &Digest) -> Result<Groth16ProofJson>,
+) -> Result<Groth16ProofJson> {
+    let job = ShrinkWrapJob::new(p254_receipt, journal, control)?;
+    let tmp_dir = tempdir().context("failed to create temporary directory")?;
+    let proof_json = backend(&work_dir(&tmp_dir), &job.input, &job.claim_digest)?;
+    job.verify(journal, proof_json)
+}
+
+/// Steps of a shrink-wrap proof shared by the sync and async paths.
+#[cfg(feature = "prove")]
+struct ShrinkWrapJob {
+    version: CircuitVersion,
+    image_id: Digest,
+    input: VerifyForGuestInput,
+    claim_digest: Digest,
+}
+
+#[cfg(feature = "prove")]
+impl ShrinkWrapJob {
+    /// Checks the setup manifest and file sizes, and builds the circuit input of `p254_receipt`.
+    /// The setup hashes are too slow to check for every proof, see `cargo xtask status --hashes`.
+    fn new(
+        p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+        journal: &[u8],
+        control: &CircuitVersion,
+    ) -> Result<Self> {
+        let setup_dir = setup::SetupDir::from_env()?;
+        setup_dir.validate()?;
+        let version = CircuitVersion {
+            verifying_key: setup_dir.verifying_key()?,
+            ..control.clone()
+        };
+        let image_id = p254_receipt.claim.as_value()?.pre.digest();
+        let input = VerifyForGuestInput::for_version(p254_receipt, journal, &version)?;
+        let claim_digest =
+            ShrinkBitvm2ReceiptClaim::for_version(image_id, journal.to_vec(), &version).digest();
+        Ok(Self {
+            version,
+            image_id,
+            input,
+            claim_digest,
+        })
+    }
+
+    fn verify(&self, journal: &[u8], proof_json: Groth16ProofJson) -> Result<Groth16ProofJson> {
+        verify_shrink_wrap(self.image_id, journal, proof_json, &self.version)
+    }
+}
+
+/// `SHRINK_BVM2_WORK_DIR`, or `tmp_dir` if it is not set.
+#[cfg(feature = "prove")]
+fn work_dir(tmp_dir: &tempfile::TempDir) -> std::path::PathBuf {
+    std::env::var("SHRINK_BVM2_WORK_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or(tmp_dir.path().to_path_buf())
+}
+
+/// Async variant of [shrink_wrap] which proves off the async runtime.
+///
+/// Triggering `cancel` kills the rapidsnark process and removes the work dir outputs. Witness
+/// generation and the CUDA backend can't be interrupted, so cancelling them only stops waiting
+/// for them while they run to completion on the blocking thread pool.
+#[cfg(feature = "tokio")]
+pub async fn shrink_wrap_async(
+    p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    cancel: CancellationToken,
+) -> Result<Groth16ProofJson> {
+    run_shrink_wrap_async(p254_receipt, journal, &CircuitVersion::current(), cancel).await
+}
+
+/// Same as [shrink_wrap_async], with an explicit control root and BN254 control ID, see
+/// [succinct_to_bitvm2_with_control].
+#[cfg(feature = "tokio")]
+pub async fn shrink_wrap_async_with_control(
+    p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    control_root: Digest,
+    bn254_control_id: Digest,
+    cancel: CancellationToken,
+) -> Result<Groth16ProofJson> {
+    let control = CircuitVersion::with_control(control_root, bn254_control_id);
+    run_shrink_wrap_async(p254_receipt, journal, &control, cancel).await
+}
 
+#[cfg(feature = "tokio")]
+async fn run_shrink_wrap_async(
+    p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+    journal: &[u8],
+    control: &CircuitVersion,
+    cancel: CancellationToken,
+) -> Result<Groth16ProofJson> {
+    anyhow::ensure!(!cancel.is_cancelled(), "shrink_wrap cancelled");
+    let job = ShrinkWrapJob::new(p254_receipt, journal, control)?;
     let tmp_dir = tempdir().context("failed to create temporary directory")?;
-    let work_dir = std::env::var("SHRINK_BVM2_WORK_DIR");
-    let work_dir = work_dir.as_ref().map(Path::new).unwrap_or(tmp_dir.path());
+    let work_dir = work_dir(&tmp_dir);
 
     #[cfg(feature = "cuda")]
-    let proof_json = prove::cuda::shrink_wrap(work_dir, seal_json)?;
+    let proof_json = {
+        let (input, claim_digest) = (job.input.clone(), job.claim_digest);
+        // Keep the temporary directory alive until the blocking task is done with it.
+        let task = tokio::task::spawn_blocking(move || {
+            let _tmp_dir = tmp_dir;
+            prove::cuda::shrink_wrap(&work_dir, &input, &claim_digest)
+        });
+        tokio::select! {
+            res = task => res??,
+            _ = cancel.cancelled() => anyhow::bail!("shrink_wrap cancelled"),
+        }
+    };
     #[cfg(not(feature = "cuda"))]
-    let proof_json = prove::docker::shrink_wrap(work_dir, seal_json)?;
+    let proof_json = prove::docker::shrink_wrap_async(
+        &work_dir,
+        job.input.clone(),
+        job.claim_digest,
+        &RapidsnarkBackend::from_env()?,
+        cancel,
+    )
+    .await?;
 
-    let bvm2_claim_digest: [u8; 32] = ShrinkBitvm2ReceiptClaim::ok(image_id, journal.to_vec())
-        .digest()
-        .into();
+    job.verify(journal, proof_json)
+}
+
+#[cfg(feature = "prove")]
+fn verify_shrink_wrap(
+    image_id: Digest,
+    journal: &[u8],
+    proof_json: Groth16ProofJson,
+    version: &CircuitVersion,
+) -> Result<Groth16ProofJson> {
+    let bvm2_claim_digest: [u8; 32] =
+        ShrinkBitvm2ReceiptClaim::for_version(image_id, journal.to_vec(), version)
+            .digest()
+            .into();
 
     let seal: Groth16Seal = proof_json.clone().try_into()?;
 
-    verify::verify_integrity(&seal, &bvm2_claim_digest)?;
+    verify::verify_integrity_with_key(&seal, &bvm2_claim_digest, &version.verifying_key)?;
     Ok(proof_json)
 }
+
+/// Wraps a BitVM2 seal into a Groth16 receipt whose `verifier_parameters` is the
+/// [Bitvm2ReceiptVerifierParameters] digest of the current version. Verify it with
+/// [verify::verify_receipt], [Receipt::verify] rejects these parameters.
 #[cfg(feature = "prove")]
 pub fn finalize(
     journal_bytes: Vec<u8>,
     receipt_claim: MaybePruned<ReceiptClaim>,
     seal: &Groth16Seal,
 ) -> Result<Receipt> {
-    let verifier_parameters_digest = crate::verify::verifier_parameters_digest();
+    finalize_with_key(
+        journal_bytes,
+        receipt_claim,
+        seal,
+        &Bitvm2VerifyingKey::Production,
+    )
+}
+
+/// Same as [finalize], for a seal produced with the zkey of `key`.
+#[cfg(feature = "prove")]
+pub fn finalize_with_key(
+    journal_bytes: Vec<u8>,
+    receipt_claim: MaybePruned<ReceiptClaim>,
+    seal: &Groth16Seal,
+    key: &Bitvm2VerifyingKey,
+) -> Result<Receipt> {
+    let version = CircuitVersion {
+        verifying_key: key.clone(),
+        ..CircuitVersion::current()
+    };
+    finalize_with_version(journal_bytes, receipt_claim, seal, &version)
+}
+
+/// Same as [finalize], for a seal produced with the circuit `version`.
+#[cfg(feature = "prove")]
+pub fn finalize_with_version(
+    journal_bytes: Vec<u8>,
+    receipt_claim: MaybePruned<ReceiptClaim>,
+    seal: &Groth16Seal,
+    version: &CircuitVersion,
+) -> Result<Receipt> {
+    let verifier_parameters_digest = version.verifier_parameters_digest();
     let groth16_receipt =
         Groth16Receipt::new(seal.to_vec(), receipt_claim, verifier_parameters_digest);
     let receipt = Receipt::new(
@@ -153,6 +538,64 @@ mod tests {
             "Should fail because shrink_bitvm2 only supports 32-byte journals"
         );
     }
+    #[cfg(feature = "prove")]
+    #[test]
+    fn test_external_proof() {
+        use guest::ECHO_ID;
+
+        let input = [3u8; 32];
+        // p254_receipt stands in for the external prover
+        let (succinct_receipt, p254_receipt) = test_utils::prove_echo_p254(&input);
+
+        let circuit_input = circuit_input(&succinct_receipt, &input).unwrap();
+        assert_eq!(circuit_input.journal_digest_bits.len(), 256);
+        assert!(!circuit_input.iop.is_empty());
+
+        let proof_json = shrink_wrap(&p254_receipt, &input).unwrap();
+
+        let receipt =
+            finalize_external_proof(&succinct_receipt, &input, proof_json.clone()).unwrap();
+        verify::verify_receipt(&receipt, ECHO_ID).unwrap();
+        // The proof is of the current version only
+        let other_root =
+            CircuitVersion::with_control(Digest::ZERO, CircuitVersion::current().bn254_control_id);
+        assert!(
+            finalize_external_proof_with_version(
+                &succinct_receipt,
+                &input,
+                proof_json.clone(),
+                &other_root
+            )
+            .is_err()
+        );
+        assert!(finalize_external_proof(&succinct_receipt, &[4u8; 32], proof_json).is_err());
+    }
+
+    #[cfg(feature = "prove")]
+    #[test]
+    fn test_p254_receipt() {
+        let input = [3u8; 32];
+        let (succinct_receipt, p254_receipt) = test_utils::prove_echo_p254(&input);
+
+        let current = CircuitVersion::current();
+        current.check_p254_receipt(&p254_receipt).unwrap();
+        assert!(current.check_p254_receipt(&succinct_receipt).is_err());
+        // The p254 receipt verified a receipt of another control root
+        let other_root = CircuitVersion::with_control(Digest::ZERO, current.bn254_control_id);
+        assert!(other_root.check_p254_receipt(&p254_receipt).is_err());
+        // The seal was not produced by the claimed control ID
+        let mut tampered = p254_receipt.clone();
+        tampered.control_id = Digest::ZERO;
+        let other_id = CircuitVersion::with_control(current.control_root, Digest::ZERO);
+        assert!(other_id.check_p254_receipt(&tampered).is_err());
+
+        // Receipts which already are p254 are used as is
+        assert_eq!(
+            circuit_input(&p254_receipt, &input).unwrap(),
+            VerifyForGuestInput::new(&p254_receipt, &input).unwrap()
+        );
+    }
+
     #[cfg(feature = "prove")]
     #[test]
     fn test_verify() {
@@ -176,6 +619,24 @@ mod tests {
         let succinct_receipt = receipt.inner.succinct().unwrap();
 
         let receipt = succinct_to_bitvm2(succinct_receipt, &input).unwrap();
-        receipt.verify(ECHO_ID).unwrap();
+        verify::verify_receipt(&receipt, ECHO_ID).unwrap();
+    }
+
+    #[cfg(feature = "prove")]
+    #[test]
+    fn test_verify_compressed() {
+        use guest::ECHO_ID;
+
+        let input = [3u8; 32];
+        let receipt = test_utils::prove_echo_bitvm2(&input);
+
+        let seal = Groth16Seal::from_vec(&receipt.inner.groth16().unwrap().seal).unwrap();
+        let compressed = seal::compress_seal(&seal).unwrap();
+        let bvm2_claim_digest: [u8; 32] = ShrinkBitvm2ReceiptClaim::ok(ECHO_ID, input.to_vec())
+            .digest()
+            .into();
+        verify::verify_integrity(&compressed, &bvm2_claim_digest).unwrap();
+        verify::verify_integrity(&seal, &bvm2_claim_digest).unwrap();
+        assert!(verify::verify_integrity(&compressed, &[0u8; 32]).is_err());
     }
 }
@@ -0,0 +1,351 @@
+//! [crate::succinct_to_bitvm2] split into stages whose artifacts are stored in a job directory,
+//! so that a failed job can be resumed from the last completed stage:
+//!
+//! 1. [IdentityP254Stage]: `p254_receipt.bin`, the p254 receipt and journal.
+//! 2. [WitnessStage]: `output.wtns`, the `verify_for_guest` witness.
+//! 3. [ProveStage]: `groth16_proof.json`, the Groth16 proof.
+//! 4. [FinalizeStage]: the BitVM2 receipt, which is cheap to recompute and not stored.
+//!
+//! Artifacts are written atomically, so an interrupted stage never leaves a partial artifact.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, ensure};
+use borsh::{BorshDeserialize, BorshSerialize};
+use risc0_groth16::ProofJson as Groth16ProofJson;
+use risc0_zkvm::{Digest, Receipt, ReceiptClaim, SuccinctReceipt, sha::Digestible};
+
+#[cfg(not(feature = "cuda"))]
+use crate::prove::docker::RapidsnarkBackend;
+use crate::{
+    ShrinkBitvm2ReceiptClaim,
+    prove::{
+        input::VerifyForGuestInput,
+        witgen::{WITNESS_FILE, WitnessGenerator},
+    },
+    registry::CircuitVersion,
+    setup::SetupDir,
+};
+
+const P254_FILE: &str = "p254_receipt.bin";
+const PROOF_FILE: &str = "groth16_proof.json";
+
+/// Output of [IdentityP254Stage].
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
+pub struct P254Artifact {
+    pub p254_receipt: SuccinctReceipt<ReceiptClaim>,
+    pub journal: Vec<u8>,
+    /// [CircuitVersion::verifier_parameters_digest] of the version the job was started with.
+    pub version: Digest,
+}
+
+impl P254Artifact {
+    pub fn save(&self, job_dir: &Path) -> Result<()> {
+        write_atomic(&job_dir.join(P254_FILE), &borsh::to_vec(self)?)
+    }
+
+    pub fn load(job_dir: &Path) -> Result<Option<Self>> {
+        read_artifact(&job_dir.join(P254_FILE))?
+            .map(|bytes| Ok(borsh::from_slice(&bytes)?))
+            .transpose()
+    }
+}
+
+/// Output of [WitnessStage], the encoded witness stored in the job directory.
+#[derive(Clone, Debug)]
+pub struct WitnessArtifact {
+    pub path: PathBuf,
+}
+
+impl WitnessArtifact {
+    pub fn load(job_dir: &Path) -> Result<Option<Self>> {
+        let path = job_dir.join(WITNESS_FILE);
+        Ok(path.try_exists()?.then_some(Self { path }))
+    }
+}
+
+/// Output of [ProveStage].
+#[derive(Debug)]
+pub struct ProofArtifact {
+    pub proof_json: Groth16ProofJson,
+}
+
+impl ProofArtifact {
+    pub fn save(&self, job_dir: &Path) -> Result<()> {
+        write_atomic(
+            &job_dir.join(PROOF_FILE),
+            serde_json::to_string(&self.proof_json)?.as_bytes(),
+        )
+    }
+
+    pub fn load(job_dir: &Path) -> Result<Option<Self>> {
+        read_artifact(&job_dir.join(PROOF_FILE))?
+            .map(|bytes| {
+                Ok(Self {
+                    proof_json: serde_json::from_slice(&bytes)?,
+                })
+            })
+            .transpose()
+    }
+}
+
+/// Checks the succinct receipt against the control root of the version and runs
+/// `identity_p254` on it, unless it already is a p254 receipt.
+pub struct IdentityP254Stage;
+
+impl IdentityP254Stage {
+    pub fn run(
+        &self,
+        succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+        journal: &[u8],
+        version: &CircuitVersion,
+    ) -> Result<P254Artifact> {
+        tracing::info!("identity_p254 stage");
+        let p254_receipt = crate::identity_p254(succinct_receipt, version)?;
+        Ok(P254Artifact {
+            p254_receipt,
+            journal: journal.to_vec(),
+            version: version.verifier_parameters_digest(),
+        })
+    }
+}
+
+/// Computes the `verify_for_guest` witness, checks its public output and writes it to the job
+/// directory.
+pub struct WitnessStage;
+
+impl WitnessStage {
+    pub fn run(
+        &self,
+        p254: &P254Artifact,
+        version: &CircuitVersion,
+        job_dir: &Path,
+    ) -> Result<WitnessArtifact> {
+        tracing::info!("witness stage");
+        let setup_dir = SetupDir::from_env()?;
+        let input = VerifyForGuestInput::for_version(&p254.p254_receipt, &p254.journal, version)?;
+        let image_id = p254.p254_receipt.claim.as_value()?.pre.digest();
+        let claim_digest =
+            ShrinkBitvm2ReceiptClaim::for_version(image_id, p254.journal.clone(), version).digest();
+        let witness_encoded = WitnessGenerator::shared(&setup_dir.graph_path())?
+            .calculate_checked(&input, &claim_digest, None)?;
+
+        let path = job_dir.join(WITNESS_FILE);
+        write_atomic(&path, &witness_encoded)?;
+        Ok(WitnessArtifact { path })
+    }
+}
+
+/// Proves the witness with rapidsnark, or with the CUDA prover when the `cuda` feature is
+/// enabled.
+pub struct ProveStage {
+    #[cfg(not(feature = "cuda"))]
+    backend: RapidsnarkBackend,
+}
+
+impl ProveStage {
+    #[cfg(not(feature = "cuda"))]
+    pub fn new(backend: RapidsnarkBackend) -> Self {
+        Self { backend }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            #[cfg(not(feature = "cuda"))]
+            backend: RapidsnarkBackend::from_env()?,
+        })
+    }
+
+    pub fn run(&self, witness: &WitnessArtifact, job_dir: &Path) -> Result<ProofArtifact> {
+        tracing::info!("prove stage");
+        #[cfg(not(feature = "cuda"))]
+        let proof_json = {
+            // rapidsnark reads the witness from its work dir, which is the job dir
+            ensure!(
+                witness.path == job_dir.join(WITNESS_FILE),
+                "witness must be in the job directory"
+            );
+            crate::prove::docker::prove_witness(job_dir, &self.backend)?
+        };
+        #[cfg(feature = "cuda")]
+        let proof_json = {
+            let witness_encoded = std::fs::read(&witness.path)
+                .with_context(|| format!("failed to read {}", witness.path.display()))?;
+            crate::prove::cuda::prove_witness(job_dir, &witness_encoded)?
+        };
+        Ok(ProofArtifact { proof_json })
+    }
+}
+
+/// Verifies the Groth16 proof and builds the BitVM2 receipt.
+pub struct FinalizeStage;
+
+impl FinalizeStage {
+    pub fn run(
+        &self,
+        p254: &P254Artifact,
+        version: &CircuitVersion,
+        proof: ProofArtifact,
+    ) -> Result<Receipt> {
+        tracing::info!("finalize stage");
+        let receipt_claim = p254.p254_receipt.claim.clone();
+        let image_id = receipt_claim.as_value()?.pre.digest();
+        let proof_json =
+            crate::verify_shrink_wrap(image_id, &p254.journal, proof.proof_json, version)?;
+        crate::finalize_with_version(
+            p254.journal.clone(),
+            receipt_claim,
+            &proof_json.try_into()?,
+            version,
+        )
+    }
+}
+
+/// Runs all stages in `job_dir`, skipping those whose artifact is already there.
+pub struct Pipeline {
+    job_dir: PathBuf,
+    prove_stage: ProveStage,
+    control: CircuitVersion,
+}
+
+impl Pipeline {
+    pub fn new(job_dir: impl Into<PathBuf>, prove_stage: ProveStage) -> Result<Self> {
+        let job_dir = job_dir.into();
+        std::fs::create_dir_all(&job_dir)
+            .with_context(|| format!("failed to create job directory {}", job_dir.display()))?;
+        Ok(Self {
+            job_dir,
+            prove_stage,
+            control: CircuitVersion::current(),
+        })
+    }
+
+    /// Verifies the succinct receipt against `control_root` and shrinks it with the
+    /// `identity_p254` program of `bn254_control_id`, instead of the ones of the risc0 version
+    /// this crate is built with.
+    pub fn with_control(mut self, control_root: Digest, bn254_control_id: Digest) -> Self {
+        self.control = CircuitVersion::with_control(control_root, bn254_control_id);
+        self
+    }
+
+    pub fn job_dir(&self) -> &Path {
+        &self.job_dir
+    }
+
+    pub fn run(
+        &self,
+        succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+        journal: &[u8],
+    ) -> Result<Receipt> {
+        let setup_dir = SetupDir::from_env()?;
+        setup_dir.validate()?;
+        let version = CircuitVersion {
+            verifying_key: setup_dir.verifying_key()?,
+            ..self.control.clone()
+        };
+        let p254 = match P254Artifact::load(&self.job_dir)? {
+            Some(p254) => {
+                tracing::info!("resuming from {}", self.job_dir.display());
+                ensure!(
+                    p254.journal == journal
+                        && p254.p254_receipt.claim.digest() == succinct_receipt.claim.digest(),
+                    "job directory {} belongs to another receipt",
+                    self.job_dir.display()
+                );
+                ensure!(
+                    p254.version == version.verifier_parameters_digest(),
+                    "job directory {} was started with another circuit version, control root \
+                     or setup",
+                    self.job_dir.display()
+                );
+                p254
+            }
+            None => {
+                let p254 = IdentityP254Stage.run(succinct_receipt, journal, &version)?;
+                p254.save(&self.job_dir)?;
+                p254
+            }
+        };
+
+        let proof = match ProofArtifact::load(&self.job_dir)? {
+            Some(proof) => proof,
+            None => {
+                let witness = match WitnessArtifact::load(&self.job_dir)? {
+                    Some(witness) => witness,
+                    None => WitnessStage.run(&p254, &version, &self.job_dir)?,
+                };
+                let proof = self.prove_stage.run(&witness, &self.job_dir)?;
+                proof.save(&self.job_dir)?;
+                proof
+            }
+        };
+
+        FinalizeStage.run(&p254, &version, proof)
+    }
+}
+
+fn read_artifact(path: &Path) -> Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    std::fs::write(&tmp_path, bytes)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    std::fs::rename(&tmp_path, path).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use guest::ECHO_ID;
+
+    #[test]
+    fn missing_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(P254Artifact::load(dir.path()).unwrap().is_none());
+        assert!(WitnessArtifact::load(dir.path()).unwrap().is_none());
+        assert!(ProofArtifact::load(dir.path()).unwrap().is_none());
+
+        write_atomic(&dir.path().join(PROOF_FILE), b"{}").unwrap();
+        assert!(ProofArtifact::load(dir.path()).is_err());
+    }
+
+    #[test]
+    fn resume_after_prove_failure() {
+        let input = [3u8; 32];
+        let succinct_receipt = &test_utils::prove_echo(&input);
+
+        let dir = tempfile::tempdir().unwrap();
+        let pipeline = Pipeline::new(dir.path(), ProveStage::from_env().unwrap()).unwrap();
+        let receipt = pipeline.run(succinct_receipt, &input).unwrap();
+        crate::verify::verify_receipt(&receipt, ECHO_ID).unwrap();
+
+        // Simulate a job which failed while proving
+        std::fs::remove_file(dir.path().join(PROOF_FILE)).unwrap();
+        let p254 = P254Artifact::load(dir.path()).unwrap().unwrap();
+        assert_eq!(p254.journal, input);
+        assert!(WitnessArtifact::load(dir.path()).unwrap().is_some());
+
+        let receipt = pipeline.run(succinct_receipt, &input).unwrap();
+        crate::verify::verify_receipt(&receipt, ECHO_ID).unwrap();
+        assert!(pipeline.run(succinct_receipt, &[4u8; 32]).is_err());
+
+        // The job must not be resumed with another control root
+        let control = CircuitVersion::current();
+        let other_control = Pipeline::new(dir.path(), ProveStage::from_env().unwrap())
+            .unwrap()
+            .with_control(Digest::ZERO, control.bn254_control_id);
+        let err = other_control.run(succinct_receipt, &input).unwrap_err();
+        assert!(
+            err.to_string().contains("another circuit version"),
+            "{err:?}"
+        );
+    }
+}
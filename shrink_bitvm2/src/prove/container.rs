@@ -0,0 +1,282 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use crate::prove::rapidsnark::{
+    self, ProcessOutput, RapidsnarkError, RapidsnarkOptions, env_string, env_u64,
+};
+
+/// Mount points of the setup and work directories inside the container.
+const SETUP_MOUNT: &str = "/setup";
+const WORK_MOUNT: &str = "/work";
+
+static CONTAINER_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Runs rapidsnark in a container through the docker (or podman) CLI. The image entrypoint must
+/// be rapidsnark, it is called with the zkey, witness, proof and public input paths.
+#[derive(Clone, Debug)]
+pub struct ContainerOptions {
+    /// Container CLI, e.g. `docker` or `podman`.
+    pub cli: PathBuf,
+    pub image: String,
+    /// Extra arguments passed to `<cli> run` before the image.
+    pub run_args: Vec<String>,
+    /// Kill the container if it runs for longer than this.
+    pub timeout: Option<Duration>,
+    /// Container memory limit, in bytes.
+    pub memory_limit: Option<u64>,
+}
+
+impl ContainerOptions {
+    pub fn new(image: impl Into<String>) -> Self {
+        Self {
+            cli: PathBuf::from("docker"),
+            image: image.into(),
+            run_args: Vec::new(),
+            timeout: None,
+            memory_limit: None,
+        }
+    }
+
+    /// Reads the configuration from the environment, returning `None` if
+    /// `SHRINK_BVM2_RAPIDSNARK_IMAGE` is not set:
+    ///
+    /// - `SHRINK_BVM2_RAPIDSNARK_IMAGE`: image to run.
+    /// - `SHRINK_BVM2_CONTAINER_CLI`: container CLI, defaults to `docker`.
+    /// - `SHRINK_BVM2_CONTAINER_ARGS`: whitespace separated extra `run` arguments.
+    /// - `SHRINK_BVM2_RAPIDSNARK_TIMEOUT_SECS` and `SHRINK_BVM2_RAPIDSNARK_MEMORY_LIMIT`, as for
+    ///   [RapidsnarkOptions::from_env].
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(image) = env_string("SHRINK_BVM2_RAPIDSNARK_IMAGE")? else {
+            return Ok(None);
+        };
+        let mut options = Self::new(image);
+        if let Some(cli) = env_string("SHRINK_BVM2_CONTAINER_CLI")? {
+            options.cli = cli.into();
+        }
+        if let Some(args) = env_string("SHRINK_BVM2_CONTAINER_ARGS")? {
+            options.run_args = args.split_whitespace().map(str::to_string).collect();
+        }
+        options.timeout = env_u64("SHRINK_BVM2_RAPIDSNARK_TIMEOUT_SECS")?.map(Duration::from_secs);
+        options.memory_limit = env_u64("SHRINK_BVM2_RAPIDSNARK_MEMORY_LIMIT")?;
+        Ok(Some(options))
+    }
+
+    /// Checks that the container CLI can be found.
+    pub fn check_cli(&self) -> Result<()> {
+        let cli = rapidsnark::resolve_binary(&self.cli)?;
+        tracing::info!("rapidsnark container: {} via {}", self.image, cli.display());
+        Ok(())
+    }
+
+    /// Limits used when running the container CLI process itself. The memory limit only applies
+    /// to the container through `--memory`, an address space limit on the CLI would make it fail
+    /// on its own.
+    pub(crate) fn process_options(&self) -> RapidsnarkOptions {
+        RapidsnarkOptions {
+            timeout: self.timeout,
+            memory_limit: None,
+            ..Default::default()
+        }
+    }
+
+    /// Builds the `<cli> run` command, with `setup_dir` and `work_dir` mounted and `args`
+    /// (relative to those directories) passed to the image.
+    pub(crate) fn command(
+        &self,
+        name: &str,
+        setup_dir: &Path,
+        work_dir: &Path,
+        setup_args: &[&str],
+        work_args: &[&str],
+    ) -> Command {
+        let mut cmd = Command::new(&self.cli);
+        cmd.arg("run")
+            .arg("--rm")
+            .arg(format!("--name={name}"))
+            .arg(format!("--volume={}:{SETUP_MOUNT}:ro", setup_dir.display()))
+            .arg(format!("--volume={}:{WORK_MOUNT}", work_dir.display()));
+        // Run as the owner of the work dir so the outputs can be cleaned up afterwards
+        #[cfg(unix)]
+        if let Ok(metadata) = std::fs::metadata(work_dir) {
+            use std::os::unix::fs::MetadataExt;
+            cmd.arg(format!("--user={}:{}", metadata.uid(), metadata.gid()));
+        }
+        if let Some(limit) = self.memory_limit {
+            cmd.arg(format!("--memory={limit}"));
+        }
+        cmd.args(&self.run_args)
+            .arg(&self.image)
+            .args(setup_args.iter().map(|arg| format!("{SETUP_MOUNT}/{arg}")))
+            .args(work_args.iter().map(|arg| format!("{WORK_MOUNT}/{arg}")))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        cmd
+    }
+
+    /// Unique container name, so the container can be killed if the CLI process is.
+    pub(crate) fn container_name() -> String {
+        format!(
+            "shrink-bitvm2-{}-{}",
+            std::process::id(),
+            CONTAINER_COUNTER.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    /// Killing the CLI process doesn't stop the container, so stop it explicitly after a
+    /// timeout or cancellation.
+    pub(crate) fn kill_after(
+        &self,
+        name: &str,
+        result: Result<ProcessOutput, RapidsnarkError>,
+    ) -> Result<ProcessOutput, RapidsnarkError> {
+        if matches!(
+            result,
+            Err(RapidsnarkError::TimedOut { .. } | RapidsnarkError::Cancelled { .. })
+        ) {
+            tracing::warn!("killing rapidsnark container {name}");
+            let status = Command::new(&self.cli)
+                .arg("kill")
+                .arg(name)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            if !status.is_ok_and(|status| status.success()) {
+                tracing::warn!("failed to kill rapidsnark container {name}");
+            }
+        }
+        result
+    }
+
+    /// Runs rapidsnark in the container.
+    pub(crate) fn run(
+        &self,
+        setup_dir: &Path,
+        work_dir: &Path,
+        setup_args: &[&str],
+        work_args: &[&str],
+    ) -> Result<ProcessOutput, RapidsnarkError> {
+        let name = Self::container_name();
+        let mut cmd = self.command(&name, setup_dir, work_dir, setup_args, work_args);
+        let result = rapidsnark::run(&mut cmd, &self.process_options());
+        self.kill_after(&name, result)
+    }
+
+    /// Async variant of [ContainerOptions::run], which also kills the container when `cancel`
+    /// is triggered.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn run_async(
+        &self,
+        setup_dir: &Path,
+        work_dir: &Path,
+        setup_args: &[&str],
+        work_args: &[&str],
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> Result<ProcessOutput, RapidsnarkError> {
+        let name = Self::container_name();
+        let cmd = self.command(&name, setup_dir, work_dir, setup_args, work_args);
+        let result = rapidsnark::run_async(cmd, &self.process_options(), cancel).await;
+        self.kill_after(&name, result)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Writes a stand-in for the container CLI which records its arguments and, for `run`,
+    /// executes `script` with the work dir mount resolved to the host directory.
+    fn stand_in_cli(dir: &Path, script: &str) -> PathBuf {
+        let log = dir.join("cli.log");
+        let cli = dir.join("fake-docker");
+        std::fs::write(
+            &cli,
+            format!(
+                r#"#!/bin/sh
+echo "$@" >> "{log}"
+[ "$1" = run ] || exit 0
+shift
+for arg in "$@"; do
+  case "$arg" in
+    --volume=*:/work) work="${{arg#--volume=}}"; work="${{work%:/work}}";;
+  esac
+done
+{script}
+"#,
+                log = log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&cli, std::fs::Permissions::from_mode(0o755)).unwrap();
+        cli
+    }
+
+    fn log(dir: &Path) -> String {
+        std::fs::read_to_string(dir.join("cli.log")).unwrap()
+    }
+
+    #[test]
+    fn runs_image_with_mounts() {
+        let dir = tempfile::tempdir().unwrap();
+        let setup_dir = dir.path().join("setup");
+        let work_dir = dir.path().join("work");
+        std::fs::create_dir_all(&setup_dir).unwrap();
+        std::fs::create_dir_all(&work_dir).unwrap();
+
+        let mut options = ContainerOptions::new("rapidsnark:test");
+        options.cli = stand_in_cli(dir.path(), r#"echo proof > "$work/proof.json""#);
+        options.memory_limit = Some(1 << 30);
+        options
+            .run(
+                &setup_dir,
+                &work_dir,
+                &["circuit.zkey"],
+                &["output.wtns", "proof.json"],
+            )
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(work_dir.join("proof.json")).unwrap(),
+            "proof\n"
+        );
+        let log = log(dir.path());
+        assert!(log.starts_with("run --rm --name=shrink-bitvm2-"));
+        assert!(log.contains(&format!("--volume={}:/setup:ro", setup_dir.display())));
+        assert!(log.contains(&format!("--volume={}:/work", work_dir.display())));
+        assert!(log.contains("--memory=1073741824"));
+        assert_eq!(options.process_options().memory_limit, None);
+        assert!(
+            log.trim_end().ends_with(
+                "rapidsnark:test /setup/circuit.zkey /work/output.wtns /work/proof.json"
+            )
+        );
+    }
+
+    #[test]
+    fn reports_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = ContainerOptions::new("rapidsnark:test");
+        options.cli = stand_in_cli(dir.path(), "echo 'invalid zkey' >&2; exit 1");
+        let err = options.run(dir.path(), dir.path(), &[], &[]).unwrap_err();
+        assert!(matches!(err, RapidsnarkError::Failed { code: 1, .. }));
+        assert!(err.to_string().contains("invalid zkey"));
+    }
+
+    #[test]
+    fn kills_container_on_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = ContainerOptions::new("rapidsnark:test");
+        options.cli = stand_in_cli(dir.path(), "exec sleep 10");
+        options.timeout = Some(Duration::from_millis(200));
+        let err = options.run(dir.path(), dir.path(), &[], &[]).unwrap_err();
+        assert!(matches!(err, RapidsnarkError::TimedOut { .. }));
+        assert!(log(dir.path()).contains("\nkill shrink-bitvm2-"));
+    }
+}
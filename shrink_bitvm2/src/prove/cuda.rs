@@ -17,45 +17,67 @@ use std::path::Path;
 use anyhow::{Context as _, Result};
 use risc0_groth16_sys::{ProverParams, SetupParams, WitnessParams};
 
-use crate::prove::witgen::calculate_witness;
+use crate::{
+    prove::{
+        input::VerifyForGuestInput,
+        witgen::{CalcWitness, WitnessCache, WitnessGenerator},
+    },
+    setup::{GRAPH_FILE, SetupDir, ZKEY_FILE},
+};
 use risc0_groth16::ProofJson as Groth16ProofJson;
+use risc0_zkvm::sha::Digest;
 
+/// Proves `input`, after checking that its witness has the public output `claim_digest`.
 pub fn shrink_wrap(
     work_dir: &Path,
-    identity_seal_json: serde_json::Value,
+    input: &VerifyForGuestInput,
+    claim_digest: &Digest,
 ) -> Result<Groth16ProofJson> {
     tracing::info!("cuda_shrink_wrap");
-    let root_dir = std::env::var("RISC0_BVM2_SETUP_DIR");
-    let root_dir = root_dir
-        .as_ref()
-        .map(Path::new)
-        .expect("must provide RISC0_BVM2_SETUP_DIR");
-
-    let mut setup_params =
-        SetupParams::new(root_dir).context("failed to create groth16 work directories")?;
-    setup_params.srs_path = root_dir.join("verify_for_guest_final.zkey").try_into()?;
+    let setup_dir = SetupDir::from_env()?;
+    let root_dir = setup_dir.path();
 
     let mut witness_params = WitnessParams::new(root_dir);
-    witness_params.graph_path = root_dir.join("verify_for_guest_graph.bin");
+    witness_params.graph_path = root_dir.join(GRAPH_FILE);
     tracing::info!("graph path: {:?}", witness_params.graph_path);
 
-    let witness = calculate_witness(
-        &witness_params.graph_path,
-        identity_seal_json.to_string().as_str(),
+    let cache = WitnessCache::from_env(&setup_dir)?;
+    let witness = WitnessGenerator::shared(&witness_params.graph_path)?.calculate_decoded(
+        input,
+        claim_digest,
+        cache.as_ref(),
     )?;
 
+    prove(root_dir, work_dir, &witness)
+}
+
+/// Proves an encoded `.wtns` witness.
+pub fn prove_witness(work_dir: &Path, witness_encoded: &[u8]) -> Result<Groth16ProofJson> {
+    tracing::info!("cuda_prove_witness");
+    prove(
+        SetupDir::from_env()?.path(),
+        work_dir,
+        &CalcWitness::decode(witness_encoded)?,
+    )
+}
+
+fn prove(root_dir: &Path, work_dir: &Path, witness: &CalcWitness) -> Result<Groth16ProofJson> {
+    let mut setup_params =
+        SetupParams::new(root_dir).context("failed to create groth16 work directories")?;
+    setup_params.srs_path = root_dir.join(ZKEY_FILE).try_into()?;
+
     tracing::info!("gpu prove start");
-    {
-        let _lock = risc0_zkp::hal::cuda::singleton().lock();
-
-        let prover_params = ProverParams::new(work_dir, witness.as_ptr())
-            .context("failed to create groth16 prover parameters")?;
-        risc0_groth16_sys::prove(&prover_params, &setup_params)
-            .context("failed to run groth16 prove operation")?;
-        tracing::info!("prover done");
-
-        let contents = std::fs::read_to_string(prover_params.proof_path.as_path())
-            .context("failed to read groth16 prove output file")?;
-        serde_json::from_str(&contents).context("failed to decode groth16 prove output file JSON")
-    }
+    let _lock = risc0_zkp::hal::cuda::singleton().lock();
+
+    let prover_params = ProverParams::new(work_dir, witness.as_ptr())
+        .context("failed to create groth16 prover parameters")?;
+    risc0_groth16_sys::prove(&prover_params, &setup_params)
+        .context("failed to run groth16 prove operation")?;
+    tracing::info!("prover done");
+
+    let contents = std::fs::read_to_string(prover_params.proof_path.as_path())
+        .context("failed to read groth16 prove output file")?;
+    let proof_json = serde_json::from_str(&contents)
+        .context("failed to decode groth16 prove output file JSON")?;
+    Ok(proof_json)
 }
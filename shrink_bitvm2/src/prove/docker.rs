@@ -1,44 +1,364 @@
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
-use crate::prove::witgen::calculate_witness_encoded;
+use crate::{
+    prove::{
+        container::ContainerOptions,
+        input::VerifyForGuestInput,
+        rapidsnark::{self, ProcessOutput, RapidsnarkError, RapidsnarkOptions},
+        witgen::{WITNESS_FILE, WitnessCache, WitnessGenerator},
+    },
+    setup::{GRAPH_FILE, SetupDir, ZKEY_FILE},
+};
 
 use anyhow::Result;
 use risc0_groth16::ProofJson as Groth16ProofJson;
-use std::process::Command;
+use risc0_zkvm::sha::Digest;
 
+const PROOF_FILE: &str = "proof.json";
+const PUBLIC_FILE: &str = "public.json";
+
+/// How rapidsnark is run: either a binary on the host, or a container image.
+///
+/// [RapidsnarkBackend::from_env] checks that the backend is usable, backends built from their
+/// options directly should be checked once with [RapidsnarkBackend::check].
+#[derive(Clone, Debug)]
+pub enum RapidsnarkBackend {
+    Host(RapidsnarkOptions),
+    Container(ContainerOptions),
+}
+
+impl RapidsnarkBackend {
+    /// Uses the container backend if `SHRINK_BVM2_RAPIDSNARK_IMAGE` is set, and the host binary
+    /// otherwise.
+    ///
+    /// The environment is read and the backend checked on the first successful call only, later
+    /// calls return the same backend.
+    pub fn from_env() -> Result<Self> {
+        static BACKEND: OnceLock<RapidsnarkBackend> = OnceLock::new();
+        if let Some(backend) = BACKEND.get() {
+            return Ok(backend.clone());
+        }
+        let backend = match ContainerOptions::from_env()? {
+            Some(options) => Self::Container(options),
+            None => Self::Host(RapidsnarkOptions::from_env()?),
+        };
+        backend.check()?;
+        Ok(BACKEND.get_or_init(|| backend).clone())
+    }
+
+    /// Checks that the rapidsnark binary, or the container CLI, can be run.
+    pub fn check(&self) -> Result<()> {
+        match self {
+            Self::Host(options) => options.check_binary().map(|_| ()),
+            Self::Container(options) => options.check_cli(),
+        }
+    }
+
+    fn run(&self, paths: &RapidsnarkPaths) -> Result<ProcessOutput, RapidsnarkError> {
+        match self {
+            Self::Host(options) => {
+                let mut cmd = options.command();
+                cmd.args(paths.args());
+                rapidsnark::run(&mut cmd, options)
+            }
+            Self::Container(options) => options.run(
+                &paths.setup_dir,
+                &paths.work_dir,
+                &[ZKEY_FILE],
+                &[WITNESS_FILE, PROOF_FILE, PUBLIC_FILE],
+            ),
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn run_async(
+        &self,
+        paths: &RapidsnarkPaths,
+        cancel: &tokio_util::sync::CancellationToken,
+    ) -> Result<ProcessOutput, RapidsnarkError> {
+        match self {
+            Self::Host(options) => {
+                let mut cmd = options.command();
+                cmd.args(paths.args());
+                rapidsnark::run_async(cmd, options, cancel).await
+            }
+            Self::Container(options) => {
+                options
+                    .run_async(
+                        &paths.setup_dir,
+                        &paths.work_dir,
+                        &[ZKEY_FILE],
+                        &[WITNESS_FILE, PROOF_FILE, PUBLIC_FILE],
+                        cancel,
+                    )
+                    .await
+            }
+        }
+    }
+}
+
+impl From<RapidsnarkOptions> for RapidsnarkBackend {
+    fn from(options: RapidsnarkOptions) -> Self {
+        Self::Host(options)
+    }
+}
+
+impl From<ContainerOptions> for RapidsnarkBackend {
+    fn from(options: ContainerOptions) -> Self {
+        Self::Container(options)
+    }
+}
+
+struct RapidsnarkPaths {
+    setup_dir: PathBuf,
+    work_dir: PathBuf,
+    zkey_path: PathBuf,
+    graph_path: PathBuf,
+    witness_path: PathBuf,
+    proof_path: PathBuf,
+    public_path: PathBuf,
+}
+
+impl RapidsnarkPaths {
+    fn new(work_dir: &Path) -> Result<Self> {
+        Ok(Self::for_setup(SetupDir::from_env()?.path(), work_dir))
+    }
+
+    fn for_setup(root_dir: &Path, work_dir: &Path) -> Self {
+        Self {
+            setup_dir: root_dir.to_path_buf(),
+            work_dir: work_dir.to_path_buf(),
+            zkey_path: root_dir.join(ZKEY_FILE),
+            graph_path: root_dir.join(GRAPH_FILE),
+            witness_path: work_dir.join(WITNESS_FILE),
+            proof_path: work_dir.join(PROOF_FILE),
+            public_path: work_dir.join(PUBLIC_FILE),
+        }
+    }
+
+    fn write_witness(
+        &self,
+        input: &VerifyForGuestInput,
+        claim_digest: &Digest,
+        cache: Option<&WitnessCache>,
+    ) -> Result<()> {
+        let witness_encoded = WitnessGenerator::shared(&self.graph_path)?.calculate_checked(
+            input,
+            claim_digest,
+            cache,
+        )?;
+        std::fs::write(&self.witness_path, witness_encoded)?;
+        Ok(())
+    }
+
+    fn args(&self) -> [&Path; 4] {
+        [
+            &self.zkey_path,
+            &self.witness_path,
+            &self.proof_path,
+            &self.public_path,
+        ]
+    }
+
+    fn read_proof(&self) -> Result<Groth16ProofJson> {
+        let proof_content = std::fs::read_to_string(&self.proof_path)?;
+
+        let proof_json: Groth16ProofJson =
+            serde_json::from_str(proof_content.trim_matches(char::from(0)))?;
+
+        Ok(proof_json)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn remove_outputs(&self) {
+        for path in [&self.witness_path, &self.proof_path, &self.public_path] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Proves `input`, after checking that its witness has the public output `claim_digest`.
 pub fn shrink_wrap(
     work_dir: &Path,
-    identity_seal_json: serde_json::Value,
+    input: &VerifyForGuestInput,
+    claim_digest: &Digest,
+    backend: &RapidsnarkBackend,
 ) -> Result<Groth16ProofJson> {
     tracing::info!("rapidsnark_shrink_wrap");
-    let root_dir = std::env::var("RISC0_BVM2_SETUP_DIR");
-    let root_dir = root_dir
-        .as_ref()
-        .map(Path::new)
-        .expect("must provide RISC0_BVM2_SETUP_DIR");
+    let paths = RapidsnarkPaths::new(work_dir)?;
+    let cache = WitnessCache::from_env(&SetupDir::new(&paths.setup_dir))?;
+    paths.write_witness(input, claim_digest, cache.as_ref())?;
+    prove(&paths, backend)
+}
+
+/// Proves the witness previously written to [WITNESS_FILE] in `work_dir`.
+pub fn prove_witness(work_dir: &Path, backend: &RapidsnarkBackend) -> Result<Groth16ProofJson> {
+    tracing::info!("rapidsnark_prove_witness");
+    prove(&RapidsnarkPaths::new(work_dir)?, backend)
+}
+
+fn prove(paths: &RapidsnarkPaths, backend: &RapidsnarkBackend) -> Result<Groth16ProofJson> {
+    let output = backend.run(paths)?;
+    tracing::debug!("rapidsnark stdout: {}", output.stdout);
+    paths.read_proof()
+}
+
+/// Async variant of [shrink_wrap]. Witness generation runs on the blocking thread pool and
+/// rapidsnark is killed, and its outputs removed, when `cancel` is triggered.
+///
+/// `cancel` is checked before each stage, but witness generation itself can't be interrupted:
+/// cancelling it returns right away while the blocking task runs to completion in the
+/// background, and removes its output when done.
+#[cfg(feature = "tokio")]
+pub async fn shrink_wrap_async(
+    work_dir: &Path,
+    input: VerifyForGuestInput,
+    claim_digest: Digest,
+    backend: &RapidsnarkBackend,
+    cancel: tokio_util::sync::CancellationToken,
+) -> Result<Groth16ProofJson> {
+    tracing::info!("rapidsnark_shrink_wrap_async");
+    let paths = std::sync::Arc::new(RapidsnarkPaths::new(work_dir)?);
+    run_shrink_wrap_async(paths, input, claim_digest, backend, cancel).await
+}
+
+#[cfg(feature = "tokio")]
+async fn run_shrink_wrap_async(
+    paths: std::sync::Arc<RapidsnarkPaths>,
+    input: VerifyForGuestInput,
+    claim_digest: Digest,
+    backend: &RapidsnarkBackend,
+    cancel: tokio_util::sync::CancellationToken,
+) -> Result<Groth16ProofJson> {
+    anyhow::ensure!(
+        !cancel.is_cancelled(),
+        "shrink_wrap cancelled before witness generation"
+    );
+    let cache = WitnessCache::from_env(&SetupDir::new(&paths.setup_dir))?;
+
+    let witness_task = tokio::task::spawn_blocking({
+        let paths = paths.clone();
+        let cache = cache.clone();
+        let cancel = cancel.clone();
+        move || {
+            paths.write_witness(&input, &claim_digest, cache.as_ref())?;
+            // The task can outlive a cancelled caller, don't leave its output behind
+            if cancel.is_cancelled() {
+                paths.remove_outputs();
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+    });
+    tokio::select! {
+        res = witness_task => res??,
+        _ = cancel.cancelled() => {
+            paths.remove_outputs();
+            anyhow::bail!("shrink_wrap cancelled during witness generation");
+        }
+    }
+
+    prove_async(&paths, backend, &cancel).await
+}
+
+#[cfg(feature = "tokio")]
+async fn prove_async(
+    paths: &RapidsnarkPaths,
+    backend: &RapidsnarkBackend,
+    cancel: &tokio_util::sync::CancellationToken,
+) -> Result<Groth16ProofJson> {
+    if cancel.is_cancelled() {
+        paths.remove_outputs();
+        anyhow::bail!("shrink_wrap cancelled before proving");
+    }
+    match backend.run_async(paths, cancel).await {
+        Ok(output) => tracing::debug!("rapidsnark stdout: {}", output.stdout),
+        Err(err) => {
+            if matches!(err, RapidsnarkError::Cancelled { .. }) {
+                paths.remove_outputs();
+            }
+            return Err(err.into());
+        }
+    }
+    paths.read_proof()
+}
+
+#[cfg(all(test, unix, feature = "tokio"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio_util::sync::CancellationToken;
 
-    let graph_path = root_dir.join("verify_for_guest_graph.bin");
-    let witness_path = work_dir.join("output.wtns");
-    let proof_path = work_dir.join("proof.json");
-    let public_path = work_dir.join("public.json");
+    use super::*;
 
-    let witness_encoded =
-        calculate_witness_encoded(&graph_path, identity_seal_json.to_string().as_str())?;
-    std::fs::write(&witness_path, witness_encoded)?;
+    /// `sh` standing in for rapidsnark, ignoring the zkey, witness and output paths.
+    fn sh_backend(script: &str) -> RapidsnarkBackend {
+        RapidsnarkBackend::Host(RapidsnarkOptions {
+            binary: "sh".into(),
+            extra_args: vec!["-c".into(), script.into()],
+            ..Default::default()
+        })
+    }
 
-    let status = Command::new("rapidsnark")
-        .arg(root_dir.join("verify_for_guest_final.zkey"))
-        .arg(witness_path)
-        .arg(&proof_path)
-        .arg(public_path)
-        .status()?;
+    fn empty_input() -> VerifyForGuestInput {
+        VerifyForGuestInput {
+            iop: Vec::new(),
+            journal_digest_bits: Vec::new(),
+            pre_state_digest_bits: Vec::new(),
+            post_state_digest_bits: Vec::new(),
+            id_bn254_fr_bits: Vec::new(),
+            control_root: Default::default(),
+        }
+    }
 
-    anyhow::ensure!(status.success(), "rapidsnark failed: {:?}", status.code());
+    #[tokio::test]
+    async fn cancelled_before_witness_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        // The setup dir has no graph, so witness generation would fail with another error
+        let paths = std::sync::Arc::new(RapidsnarkPaths::for_setup(dir.path(), dir.path()));
+        let cancel = CancellationToken::new();
+        cancel.cancel();
 
-    let proof_content = std::fs::read_to_string(&proof_path)?;
+        let err = run_shrink_wrap_async(
+            paths,
+            empty_input(),
+            Digest::ZERO,
+            &sh_backend("exit 1"),
+            cancel,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("cancelled"), "{err:?}");
+    }
 
-    let proof_json: Groth16ProofJson =
-        serde_json::from_str(proof_content.trim_matches(char::from(0)))?;
+    #[tokio::test]
+    async fn cancel_kills_rapidsnark() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = RapidsnarkPaths::for_setup(dir.path(), dir.path());
+        std::fs::write(&paths.witness_path, b"witness").unwrap();
+        let backend = sh_backend("exec sleep 10");
+        backend.check().unwrap();
 
-    Ok(proof_json)
+        let cancel = CancellationToken::new();
+        tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                cancel.cancel();
+            }
+        });
+        let start = Instant::now();
+        let err = prove_async(&paths, &backend, &cancel).await.unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(
+            matches!(
+                err.downcast_ref::<RapidsnarkError>(),
+                Some(RapidsnarkError::Cancelled { .. })
+            ),
+            "{err:?}"
+        );
+        assert!(!paths.witness_path.exists());
+    }
 }
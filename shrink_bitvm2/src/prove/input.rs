@@ -0,0 +1,290 @@
+use anyhow::{Context, Result, bail, ensure};
+use num_bigint::BigUint;
+use num_traits::Num;
+use risc0_zkvm::{ReceiptClaim, SuccinctReceipt, sha::Digest, sha::Digestible};
+use serde::{Deserialize, Serialize};
+
+use super::iop::{IopType, SEAL_ELEMS, seal_to_iop, seal_types};
+use crate::registry::CircuitVersion;
+
+/// Input of the `verify_for_guest` circuit, serialized to exactly the JSON expected by the
+/// witness generator.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyForGuestInput {
+    /// The p254 seal, as decimal field elements.
+    pub iop: Vec<String>,
+    pub journal_digest_bits: Vec<u8>,
+    pub pre_state_digest_bits: Vec<String>,
+    pub post_state_digest_bits: Vec<String>,
+    pub id_bn254_fr_bits: Vec<String>,
+    /// The succinct control root split in two 128-bit halves, as decimal `[low, high]`.
+    pub control_root: [String; 2],
+}
+
+impl VerifyForGuestInput {
+    /// Builds the circuit input for shrinking `p254_receipt` with `journal`.
+    pub fn new(p254_receipt: &SuccinctReceipt<ReceiptClaim>, journal: &[u8]) -> Result<Self> {
+        Self::for_version(p254_receipt, journal, &CircuitVersion::current())
+    }
+
+    /// Same as [VerifyForGuestInput::new], with the control root and BN254 control ID of
+    /// `version`. Fails if `p254_receipt` is not a valid receipt of that BN254 control ID, see
+    /// [CircuitVersion::check_p254_receipt].
+    pub fn for_version(
+        p254_receipt: &SuccinctReceipt<ReceiptClaim>,
+        journal: &[u8],
+        version: &CircuitVersion,
+    ) -> Result<Self> {
+        version.check_p254_receipt(p254_receipt)?;
+        let iop = seal_to_iop(&p254_receipt.get_seal_bytes())?;
+
+        Self::from_parts(
+            iop,
+            journal,
+            p254_receipt.claim.as_value()?,
+            p254_receipt.control_id,
+            version.control_root,
+        )
+    }
+
+    pub(crate) fn from_parts(
+        iop: Vec<String>,
+        journal: &[u8],
+        claim: &ReceiptClaim,
+        control_id: Digest,
+        control_root: Digest,
+    ) -> Result<Self> {
+        let journal_digest_bits = journal
+            .iter()
+            .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+            .collect();
+
+        let mut id_bn254_fr_bits = string_bits(&control_id);
+        // remove 248th and 249th bits
+        id_bn254_fr_bits.drain(248..250);
+
+        let mut control_root_bytes: [u8; 32] = control_root.as_bytes().try_into()?;
+        control_root_bytes.reverse();
+        let control_root_hex = hex::encode(control_root_bytes);
+        let a1_dec = to_decimal(&control_root_hex[0..32]).context("a1_str returned None")?;
+        let a0_dec = to_decimal(&control_root_hex[32..64]).context("a0_str returned None")?;
+
+        Ok(Self {
+            iop,
+            journal_digest_bits,
+            pre_state_digest_bits: string_bits(&claim.pre.digest()),
+            post_state_digest_bits: string_bits(&claim.post.digest()),
+            id_bn254_fr_bits,
+            control_root: [a0_dec, a1_dec],
+        })
+    }
+
+    /// Checks the shape and range of every field, naming the first invalid one. A witness
+    /// failure on an input which passes this check comes from a seal or claim the circuit
+    /// doesn't accept, see [VerifyForGuestInput::diff].
+    pub fn check(&self) -> Result<()> {
+        ensure!(
+            self.iop.len() == SEAL_ELEMS,
+            "iop has {} elements, expected {SEAL_ELEMS}",
+            self.iop.len()
+        );
+        let bn254_r = BigUint::from_str_radix(BN254_R, 10).unwrap();
+        for (i, (value, iop_type)) in self.iop.iter().zip(seal_types()).enumerate() {
+            let modulus = match iop_type {
+                IopType::Fp => BigUint::from(BABY_BEAR_P),
+                IopType::Digest => bn254_r.clone(),
+            };
+            match BigUint::from_str_radix(value, 10) {
+                Ok(value) if value < modulus => {}
+                _ => bail!("iop[{i}] = {value:?} is not a {iop_type:?} element"),
+            }
+        }
+
+        ensure!(
+            self.journal_digest_bits.len() == 256,
+            "journal_digest_bits has {} bits, expected 256, the journal must be 32 bytes",
+            self.journal_digest_bits.len()
+        );
+        if let Some(i) = self.journal_digest_bits.iter().position(|&bit| bit > 1) {
+            bail!("journal_digest_bits[{i}] is not a bit");
+        }
+        check_string_bits("pre_state_digest_bits", &self.pre_state_digest_bits, 256)?;
+        check_string_bits("post_state_digest_bits", &self.post_state_digest_bits, 256)?;
+        check_string_bits("id_bn254_fr_bits", &self.id_bn254_fr_bits, 254)?;
+
+        for (i, limb) in self.control_root.iter().enumerate() {
+            match BigUint::from_str_radix(limb, 10) {
+                Ok(limb) if limb.bits() <= 128 => {}
+                _ => bail!("control_root[{i}] = {limb:?} is not a 128-bit limb"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the fields which differ from `expected`, e.g. the input of a known good proof.
+    pub fn diff(&self, expected: &Self) -> Vec<&'static str> {
+        let fields = [
+            ("iop", self.iop == expected.iop),
+            (
+                "journal_digest_bits",
+                self.journal_digest_bits == expected.journal_digest_bits,
+            ),
+            (
+                "pre_state_digest_bits",
+                self.pre_state_digest_bits == expected.pre_state_digest_bits,
+            ),
+            (
+                "post_state_digest_bits",
+                self.post_state_digest_bits == expected.post_state_digest_bits,
+            ),
+            (
+                "id_bn254_fr_bits",
+                self.id_bn254_fr_bits == expected.id_bn254_fr_bits,
+            ),
+            ("control_root", self.control_root == expected.control_root),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, equal)| !equal)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+const BABY_BEAR_P: u32 = 15 * (1 << 27) + 1;
+const BN254_R: &str =
+    "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+
+fn check_string_bits(name: &str, bits: &[String], len: usize) -> Result<()> {
+    ensure!(
+        bits.len() == len,
+        "{name} has {} bits, expected {len}",
+        bits.len()
+    );
+    if let Some(i) = bits.iter().position(|bit| bit != "0" && bit != "1") {
+        bail!("{name}[{i}] = {:?} is not a bit", bits[i]);
+    }
+    Ok(())
+}
+
+/// Big-endian bits of `digest`, as the strings `"0"` and `"1"`.
+fn string_bits(digest: &Digest) -> Vec<String> {
+    digest
+        .as_bytes()
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |i| ((byte >> i) & 1).to_string()))
+        .collect()
+}
+
+fn to_decimal(s: &str) -> Option<String> {
+    let int = BigUint::from_str_radix(s, 16).ok();
+    int.map(|n| n.to_str_radix(10))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> VerifyForGuestInput {
+        let claim = ReceiptClaim::ok(Digest::from([1u32; 8]), vec![2u8; 32]);
+        VerifyForGuestInput::from_parts(
+            vec!["1".to_string(), "2".to_string()],
+            &[0x80; 32],
+            &claim,
+            Digest::from_bytes([0xff; 32]),
+            Digest::from_bytes(std::array::from_fn(|i| i as u8)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn shape() {
+        let input = input();
+        assert_eq!(input.journal_digest_bits.len(), 256);
+        assert_eq!(
+            &input.journal_digest_bits[..9],
+            &[1, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+        assert_eq!(input.pre_state_digest_bits.len(), 256);
+        assert_eq!(input.post_state_digest_bits.len(), 256);
+        // Bits 248 and 249 are removed
+        assert_eq!(input.id_bn254_fr_bits.len(), 254);
+        assert!(input.id_bn254_fr_bits.iter().all(|bit| bit == "1"));
+
+        // The control root is read little-endian, so bytes 0..16 are the low half
+        let low = BigUint::from_bytes_le(&(0..16).collect::<Vec<u8>>());
+        let high = BigUint::from_bytes_le(&(16..32).collect::<Vec<u8>>());
+        assert_eq!(input.control_root, [low.to_string(), high.to_string()]);
+
+        let json: serde_json::Value = serde_json::from_str(&input.to_json().unwrap()).unwrap();
+        let mut keys: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "control_root",
+                "id_bn254_fr_bits",
+                "iop",
+                "journal_digest_bits",
+                "post_state_digest_bits",
+                "pre_state_digest_bits",
+            ]
+        );
+        assert!(json["journal_digest_bits"][0].is_u64());
+        assert!(json["pre_state_digest_bits"][0].is_string());
+        assert!(json["id_bn254_fr_bits"][0].is_string());
+        assert!(json["control_root"][0].is_string());
+    }
+
+    #[test]
+    fn check() {
+        let mut input = input();
+        input.iop = vec!["0".to_string(); SEAL_ELEMS];
+        input.check().unwrap();
+
+        let mut bad = input.clone();
+        bad.iop[0] = BABY_BEAR_P.to_string();
+        assert!(bad.check().unwrap_err().to_string().contains("iop[0]"));
+
+        let mut bad = input.clone();
+        bad.journal_digest_bits.pop();
+        assert!(bad.check().unwrap_err().to_string().contains("journal"));
+
+        let mut bad = input.clone();
+        bad.id_bn254_fr_bits[3] = "2".to_string();
+        assert!(
+            bad.check()
+                .unwrap_err()
+                .to_string()
+                .contains("id_bn254_fr_bits[3]")
+        );
+
+        let mut bad = input.clone();
+        bad.control_root[1] = (BigUint::from(1u8) << 128u32).to_string();
+        assert!(
+            bad.check()
+                .unwrap_err()
+                .to_string()
+                .contains("control_root[1]")
+        );
+        assert_eq!(bad.diff(&input), ["control_root"]);
+        assert!(input.diff(&input).is_empty());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let input = input();
+        assert_eq!(
+            VerifyForGuestInput::from_json(&input.to_json().unwrap()).unwrap(),
+            input
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Conversion of a p254 recursion seal to the `iop` input of the `verify_for_guest` circuit,
+//! matching `risc0_groth16`'s `seal_to_json`, which is not part of its public API.
+
+use anyhow::{Result, ensure};
+use num_bigint::BigUint;
+use risc0_zkp::{core::digest::DIGEST_WORDS, field::baby_bear::BabyBearElem};
+
+// The layout is the `K_SEAL_WORDS`, `K_SEAL_ELEMS` and `K_SEAL_TYPES` table of risc0-groth16
+// 2.0.3 (`src/seal_format.rs`) for the recursion circuit seal of `identity_p254`, run-length
+// encoded. `matches_risc0_groth16` checks it against `to_json` of the pinned risc0-groth16 3.0.2
+// (ec2/risc0 rev 193ebbb). It must be regenerated if risc0-circuit-recursion changes that seal.
+
+/// Number of u32 words of the seal read by the circuit.
+const SEAL_WORDS: usize = 55667;
+/// Number of field elements and digests of the seal read by the circuit.
+pub(crate) const SEAL_ELEMS: usize = 25749;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum IopType {
+    /// A BabyBear element in Montgomery form, one word.
+    Fp,
+    /// A Poseidon254 digest, eight words.
+    Digest,
+}
+
+use IopType::{Digest, Fp};
+
+/// Run-length encoded layout of the seal before the FRI queries.
+const SEAL_HEADER: [(IopType, usize); 5] =
+    [(Fp, 33), (Digest, 128), (Fp, 2636), (Digest, 96), (Fp, 268)];
+
+/// Run-length encoded layout of each FRI query. The seal ends after the Merkle branch of the
+/// last query, see [SEAL_ELEMS].
+const SEAL_QUERY: [(IopType, usize); 14] = [
+    (Digest, 15),
+    (Fp, 23),
+    (Digest, 15),
+    (Fp, 128),
+    (Digest, 15),
+    (Fp, 16),
+    (Digest, 15),
+    (Fp, 64),
+    (Digest, 11),
+    (Fp, 64),
+    (Digest, 7),
+    (Fp, 64),
+    (Digest, 3),
+    (Fp, 12),
+];
+
+pub(crate) fn seal_types() -> impl Iterator<Item = IopType> {
+    SEAL_HEADER
+        .into_iter()
+        .chain(SEAL_QUERY.into_iter().cycle())
+        .flat_map(|(iop_type, count)| std::iter::repeat_n(iop_type, count))
+        .take(SEAL_ELEMS)
+}
+
+/// Converts the bytes of a p254 seal to the decimal field elements of the circuit `iop` input.
+pub(crate) fn seal_to_iop(seal_bytes: &[u8]) -> Result<Vec<String>> {
+    ensure!(
+        seal_bytes.len() == SEAL_WORDS * 4,
+        "p254 seal is {} bytes, expected {}",
+        seal_bytes.len(),
+        SEAL_WORDS * 4
+    );
+    let words: Vec<u32> = seal_bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+
+    let mut iop = Vec::with_capacity(SEAL_ELEMS);
+    let mut pos = 0;
+    for iop_type in seal_types() {
+        match iop_type {
+            Fp => {
+                iop.push(BabyBearElem::new_raw(words[pos]).as_u32().to_string());
+                pos += 1;
+            }
+            Digest => {
+                iop.push(digest_to_decimal(&words[pos..pos + DIGEST_WORDS]));
+                pos += DIGEST_WORDS;
+            }
+        }
+    }
+    Ok(iop)
+}
+
+/// Decimal value of a Poseidon254 digest, whose words hold the little-endian BN254 scalar.
+fn digest_to_decimal(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    BigUint::from_bytes_le(&bytes).to_str_radix(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let types: Vec<_> = seal_types().collect();
+        assert_eq!(types.len(), SEAL_ELEMS);
+        let words: usize = types
+            .iter()
+            .map(|iop_type| match iop_type {
+                Fp => 1,
+                Digest => DIGEST_WORDS,
+            })
+            .sum();
+        assert_eq!(words, SEAL_WORDS);
+        assert_eq!(types.last(), Some(&Digest));
+    }
+
+    #[test]
+    fn converts_elements() {
+        let mut seal_bytes = vec![0u8; SEAL_WORDS * 4];
+        // The first element is a field element in Montgomery form
+        seal_bytes[..4].copy_from_slice(&BabyBearElem::new(7).as_u32_montgomery().to_le_bytes());
+        // The first digest follows the 33 field elements of the header
+        seal_bytes[33 * 4] = 1;
+        seal_bytes[33 * 4 + 31] = 2;
+
+        let iop = seal_to_iop(&seal_bytes).unwrap();
+        assert_eq!(iop.len(), SEAL_ELEMS);
+        assert_eq!(iop[0], "7");
+        assert_eq!(iop[1], "0");
+        assert_eq!(
+            iop[33],
+            ((BigUint::from(2u8) << 248u32) | BigUint::from(1u8)).to_string()
+        );
+
+        assert!(seal_to_iop(&seal_bytes[1..]).is_err());
+        seal_bytes.extend_from_slice(&[0; 4]);
+        assert!(seal_to_iop(&seal_bytes).is_err());
+    }
+
+    /// Run with `cargo test -p shrink_bitvm2 --features prove matches_risc0_groth16`.
+    #[cfg(feature = "prove")]
+    #[test]
+    fn matches_risc0_groth16() {
+        let (_, p254_receipt) = crate::test_utils::prove_echo_p254(&[3u8; 32]);
+        let seal_bytes = p254_receipt.get_seal_bytes();
+
+        // The patched risc0 exposes the original conversion
+        let seal_json = risc0_groth16::prove::to_json(seal_bytes.as_slice()).unwrap();
+        let seal_json: serde_json::Value = serde_json::from_str(&seal_json).unwrap();
+        let expected: Vec<String> = serde_json::from_value(seal_json["iop"].clone()).unwrap();
+        assert_eq!(seal_to_iop(&seal_bytes).unwrap(), expected);
+    }
+}
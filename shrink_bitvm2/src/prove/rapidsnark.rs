@@ -0,0 +1,573 @@
+use std::{
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of trailing stderr lines included in error messages.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Configuration of the rapidsnark child process.
+#[derive(Clone, Debug)]
+pub struct RapidsnarkOptions {
+    /// Path to the rapidsnark binary. Bare names are looked up in `PATH`.
+    pub binary: PathBuf,
+    /// Extra arguments, passed before the zkey, witness and output paths.
+    pub extra_args: Vec<String>,
+    /// Extra environment variables for the rapidsnark process.
+    pub env: Vec<(String, String)>,
+    /// Kill rapidsnark if it runs for longer than this.
+    pub timeout: Option<Duration>,
+    /// Address space limit for rapidsnark, in bytes.
+    pub memory_limit: Option<u64>,
+}
+
+impl Default for RapidsnarkOptions {
+    fn default() -> Self {
+        Self {
+            binary: PathBuf::from("rapidsnark"),
+            extra_args: Vec::new(),
+            env: Vec::new(),
+            timeout: None,
+            memory_limit: None,
+        }
+    }
+}
+
+impl RapidsnarkOptions {
+    /// Reads the configuration from the environment:
+    ///
+    /// - `SHRINK_BVM2_RAPIDSNARK_BIN`: path to the rapidsnark binary.
+    /// - `SHRINK_BVM2_RAPIDSNARK_ARGS`: whitespace separated extra arguments.
+    /// - `SHRINK_BVM2_RAPIDSNARK_ENV`: whitespace separated `KEY=VALUE` pairs.
+    /// - `SHRINK_BVM2_RAPIDSNARK_TIMEOUT_SECS`: timeout in seconds.
+    /// - `SHRINK_BVM2_RAPIDSNARK_MEMORY_LIMIT`: memory limit in bytes.
+    ///
+    /// Unset variables keep the defaults.
+    pub fn from_env() -> Result<Self> {
+        let mut options = Self::default();
+        if let Some(binary) = env_string("SHRINK_BVM2_RAPIDSNARK_BIN")? {
+            options.binary = binary.into();
+        }
+        if let Some(args) = env_string("SHRINK_BVM2_RAPIDSNARK_ARGS")? {
+            options.extra_args = args.split_whitespace().map(str::to_string).collect();
+        }
+        if let Some(env) = env_string("SHRINK_BVM2_RAPIDSNARK_ENV")? {
+            options.env = env
+                .split_whitespace()
+                .map(|pair| {
+                    pair.split_once('=')
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .with_context(|| {
+                            format!("SHRINK_BVM2_RAPIDSNARK_ENV: expected KEY=VALUE, got {pair:?}")
+                        })
+                })
+                .collect::<Result<_>>()?;
+        }
+        options.timeout = env_u64("SHRINK_BVM2_RAPIDSNARK_TIMEOUT_SECS")?.map(Duration::from_secs);
+        options.memory_limit = env_u64("SHRINK_BVM2_RAPIDSNARK_MEMORY_LIMIT")?;
+        Ok(options)
+    }
+
+    /// Builds the rapidsnark command with the extra arguments, environment and memory limit
+    /// applied.
+    pub(crate) fn command(&self) -> Command {
+        self.command_for(&self.binary)
+    }
+
+    fn command_for(&self, program: &Path) -> Command {
+        let mut cmd = Command::new(program);
+        cmd.args(&self.extra_args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(unix)]
+        if let Some(limit) = self.memory_limit {
+            use std::os::unix::process::CommandExt;
+            // SAFETY: the closure only calls setrlimit, which is async-signal-safe.
+            unsafe {
+                cmd.pre_exec(move || rlimit::setrlimit(rlimit::Resource::AS, limit, limit));
+            }
+        }
+        cmd
+    }
+
+    /// Checks that the binary exists and responds, returning the version it reports.
+    pub fn check_binary(&self) -> Result<String> {
+        let binary = resolve_binary(&self.binary)?;
+
+        let mut cmd = Command::new(&binary);
+        cmd.arg("--version")
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let check_options = Self {
+            timeout: Some(VERSION_CHECK_TIMEOUT),
+            ..Self::default()
+        };
+        // Not every rapidsnark build knows `--version`, a usage message on failure still shows
+        // that the binary runs.
+        let output = match run(&mut cmd, &check_options) {
+            Ok(output) | Err(RapidsnarkError::Failed { output, .. }) => output,
+            Err(err) => {
+                return Err(err).with_context(|| format!("{} does not respond", binary.display()));
+            }
+        };
+
+        let version = output
+            .stdout
+            .lines()
+            .chain(output.stderr.lines())
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("unknown version")
+            .to_string();
+        tracing::info!("rapidsnark: {} ({version})", binary.display());
+        Ok(version)
+    }
+}
+
+/// Resolves `binary` against `PATH` if it is a bare program name.
+pub(crate) fn resolve_binary(binary: &Path) -> Result<PathBuf> {
+    if binary.components().count() > 1 {
+        anyhow::ensure!(binary.is_file(), "binary not found: {}", binary.display());
+        return Ok(binary.to_path_buf());
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+        .with_context(|| format!("{} not found in PATH", binary.display()))
+}
+
+pub(crate) fn env_string(name: &str) -> Result<Option<String>> {
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(err) => Err(err).context(name.to_string()),
+    }
+}
+
+pub(crate) fn env_u64(name: &str) -> Result<Option<u64>> {
+    env_string(name)?
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("{name} must be an integer, got {value:?}"))
+        })
+        .transpose()
+}
+
+/// Captured stdout and stderr of a rapidsnark run.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ProcessOutput {
+    fn new(stdout: &[u8], stderr: &[u8]) -> Self {
+        Self {
+            stdout: String::from_utf8_lossy(stdout).into_owned(),
+            stderr: String::from_utf8_lossy(stderr).into_owned(),
+        }
+    }
+
+    fn stderr_tail(&self) -> String {
+        let lines: Vec<_> = self.stderr.lines().collect();
+        lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n")
+    }
+}
+
+#[derive(Debug)]
+pub enum RapidsnarkError {
+    /// The process could not be started.
+    Spawn(std::io::Error),
+    /// Waiting on the process or reading its output failed.
+    Io(std::io::Error),
+    TimedOut {
+        timeout: Duration,
+        output: ProcessOutput,
+    },
+    /// The process ran out of memory, either hitting the configured limit or the OOM killer. A
+    /// SIGKILL which this crate didn't send is assumed to come from the OOM killer.
+    OutOfMemory {
+        memory_limit: Option<u64>,
+        output: ProcessOutput,
+    },
+    /// The process was killed by a SIGKILL which this crate sent. Kills after a timeout or
+    /// cancellation are reported as [RapidsnarkError::TimedOut] and
+    /// [RapidsnarkError::Cancelled] instead.
+    Killed { output: ProcessOutput },
+    /// The process was terminated by a signal.
+    Signaled { signal: i32, output: ProcessOutput },
+    /// The process exited with a non-zero status.
+    Failed { code: i32, output: ProcessOutput },
+    /// The process was killed because proving was cancelled.
+    Cancelled { output: ProcessOutput },
+}
+
+impl RapidsnarkError {
+    pub fn output(&self) -> Option<&ProcessOutput> {
+        match self {
+            Self::Spawn(_) | Self::Io(_) => None,
+            Self::TimedOut { output, .. }
+            | Self::OutOfMemory { output, .. }
+            | Self::Killed { output }
+            | Self::Signaled { output, .. }
+            | Self::Failed { output, .. }
+            | Self::Cancelled { output } => Some(output),
+        }
+    }
+}
+
+impl fmt::Display for RapidsnarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn(err) => write!(f, "failed to start rapidsnark: {err}")?,
+            Self::Io(err) => write!(f, "failed to wait on rapidsnark: {err}")?,
+            Self::TimedOut { timeout, .. } => write!(f, "rapidsnark timed out after {timeout:?}")?,
+            Self::OutOfMemory {
+                memory_limit: Some(limit),
+                ..
+            } => write!(f, "rapidsnark ran out of memory (limit: {limit} bytes)")?,
+            Self::OutOfMemory { .. } => write!(f, "rapidsnark ran out of memory")?,
+            Self::Killed { .. } => write!(f, "rapidsnark killed")?,
+            Self::Signaled { signal, .. } => write!(f, "rapidsnark killed by signal {signal}")?,
+            Self::Failed { code, .. } => write!(f, "rapidsnark failed with exit code {code}")?,
+            Self::Cancelled { .. } => write!(f, "rapidsnark cancelled")?,
+        }
+        if let Some(output) = self.output() {
+            let tail = output.stderr_tail();
+            if !tail.is_empty() {
+                write!(f, "\nstderr:\n{tail}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RapidsnarkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Spawn(err) | Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Maps the exit status of a finished process to its output or a [RapidsnarkError].
+/// `kill_sent` tells whether this crate killed the process, in which case a SIGKILL is reported
+/// as [RapidsnarkError::Killed] instead of [RapidsnarkError::OutOfMemory].
+fn check_status(
+    status: ExitStatus,
+    output: ProcessOutput,
+    options: &RapidsnarkOptions,
+    kill_sent: bool,
+) -> Result<ProcessOutput, RapidsnarkError> {
+    if status.success() {
+        return Ok(output);
+    }
+
+    let stderr = output.stderr.to_lowercase();
+    if stderr.contains("bad_alloc") || stderr.contains("out of memory") {
+        return Err(RapidsnarkError::OutOfMemory {
+            memory_limit: options.memory_limit,
+            output,
+        });
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        // SIGKILL that we didn't send ourselves is most likely the OOM killer
+        const SIGKILL: i32 = 9;
+        if let Some(signal) = status.signal() {
+            if signal == SIGKILL && kill_sent {
+                return Err(RapidsnarkError::Killed { output });
+            }
+            if signal == SIGKILL {
+                return Err(RapidsnarkError::OutOfMemory {
+                    memory_limit: options.memory_limit,
+                    output,
+                });
+            }
+            return Err(RapidsnarkError::Signaled { signal, output });
+        }
+    }
+
+    // Container CLIs report a SIGKILL'd container, usually the OOM killer, as exit code 137
+    const CONTAINER_KILLED: i32 = 128 + 9;
+    match status.code() {
+        Some(CONTAINER_KILLED) if kill_sent => Err(RapidsnarkError::Killed { output }),
+        Some(CONTAINER_KILLED) => Err(RapidsnarkError::OutOfMemory {
+            memory_limit: options.memory_limit,
+            output,
+        }),
+        code => Err(RapidsnarkError::Failed {
+            code: code.unwrap_or(-1),
+            output,
+        }),
+    }
+}
+
+/// Runs `cmd` to completion, enforcing the timeout and capturing its output.
+pub(crate) fn run(
+    cmd: &mut Command,
+    options: &RapidsnarkOptions,
+) -> Result<ProcessOutput, RapidsnarkError> {
+    let mut child = cmd.spawn().map_err(RapidsnarkError::Spawn)?;
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        stderr.read_to_end(&mut buf).map(|_| buf)
+    });
+
+    let start = Instant::now();
+    let (status, kill_sent) = loop {
+        if let Some(status) = child.try_wait().map_err(RapidsnarkError::Io)? {
+            break (status, false);
+        }
+        if options
+            .timeout
+            .is_some_and(|timeout| start.elapsed() >= timeout)
+        {
+            child.kill().map_err(RapidsnarkError::Io)?;
+            break (child.wait().map_err(RapidsnarkError::Io)?, true);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    let stdout = stdout_reader
+        .join()
+        .expect("stdout reader panicked")
+        .map_err(RapidsnarkError::Io)?;
+    let stderr = stderr_reader
+        .join()
+        .expect("stderr reader panicked")
+        .map_err(RapidsnarkError::Io)?;
+    let output = ProcessOutput::new(&stdout, &stderr);
+
+    match (
+        check_status(status, output, options, kill_sent),
+        options.timeout,
+    ) {
+        // Any other result means the process finished before the kill landed
+        (Err(RapidsnarkError::Killed { output }), Some(timeout)) => {
+            Err(RapidsnarkError::TimedOut { timeout, output })
+        }
+        (result, _) => result,
+    }
+}
+
+/// Async variant of [run], which also kills the process when `cancel` is triggered.
+#[cfg(feature = "tokio")]
+pub(crate) async fn run_async(
+    cmd: Command,
+    options: &RapidsnarkOptions,
+    cancel: &tokio_util::sync::CancellationToken,
+) -> Result<ProcessOutput, RapidsnarkError> {
+    use tokio::io::AsyncReadExt;
+
+    let mut cmd = tokio::process::Command::from(cmd);
+    let mut child = cmd
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(RapidsnarkError::Spawn)?;
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout_reader = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).await.map(|_| buf)
+    });
+    let stderr_reader = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        stderr.read_to_end(&mut buf).await.map(|_| buf)
+    });
+
+    let timeout = async {
+        match options.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    enum Outcome {
+        Exited(ExitStatus),
+        TimedOut,
+        Cancelled,
+    }
+    let outcome = tokio::select! {
+        status = child.wait() => Outcome::Exited(status.map_err(RapidsnarkError::Io)?),
+        _ = timeout => Outcome::TimedOut,
+        _ = cancel.cancelled() => Outcome::Cancelled,
+    };
+    let (status, kill_sent) = match outcome {
+        Outcome::Exited(status) => (status, false),
+        _ => {
+            child.start_kill().map_err(RapidsnarkError::Io)?;
+            (child.wait().await.map_err(RapidsnarkError::Io)?, true)
+        }
+    };
+
+    let join = |res: Result<std::io::Result<Vec<u8>>, tokio::task::JoinError>| {
+        res.map_err(std::io::Error::other)
+            .and_then(|res| res)
+            .map_err(RapidsnarkError::Io)
+    };
+    let stdout = join(stdout_reader.await)?;
+    let stderr = join(stderr_reader.await)?;
+    let output = ProcessOutput::new(&stdout, &stderr);
+
+    // Any other result means the process finished before the kill landed
+    match (check_status(status, output, options, kill_sent), outcome) {
+        (Err(RapidsnarkError::Killed { output }), Outcome::TimedOut) => {
+            Err(RapidsnarkError::TimedOut {
+                timeout: options.timeout.expect("timeout is set"),
+                output,
+            })
+        }
+        (Err(RapidsnarkError::Killed { output }), Outcome::Cancelled) => {
+            Err(RapidsnarkError::Cancelled { output })
+        }
+        (result, _) => result,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn sh(options: &RapidsnarkOptions, script: &str) -> Command {
+        let mut cmd = options.command_for(Path::new("sh"));
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+
+    #[test]
+    fn captures_output_on_failure() {
+        let options = RapidsnarkOptions::default();
+        let err = run(
+            &mut sh(&options, "echo out; echo boom >&2; exit 3"),
+            &options,
+        )
+        .unwrap_err();
+        let RapidsnarkError::Failed { code, output } = &err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert_eq!(*code, 3);
+        assert_eq!(output.stdout, "out\n");
+        assert!(err.to_string().contains("boom"));
+    }
+
+    #[test]
+    fn times_out() {
+        let options = RapidsnarkOptions {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let err = run(&mut sh(&options, "exec sleep 10"), &options).unwrap_err();
+        assert!(matches!(err, RapidsnarkError::TimedOut { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn maps_signals() {
+        let options = RapidsnarkOptions::default();
+        let err = run(&mut sh(&options, "kill -TERM $$"), &options).unwrap_err();
+        assert!(
+            matches!(err, RapidsnarkError::Signaled { signal: 15, .. }),
+            "{err:?}"
+        );
+
+        let err = run(
+            &mut sh(&options, "echo std::bad_alloc >&2; exit 1"),
+            &options,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(err, RapidsnarkError::OutOfMemory { .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn sigkill_is_out_of_memory_unless_sent_by_us() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let sigkill = ExitStatus::from_raw(9);
+        let container_killed = ExitStatus::from_raw(137 << 8);
+        for status in [sigkill, container_killed] {
+            let options = RapidsnarkOptions::default();
+            let err = check_status(status, ProcessOutput::default(), &options, false).unwrap_err();
+            assert!(
+                matches!(err, RapidsnarkError::OutOfMemory { .. }),
+                "{err:?}"
+            );
+            let err = check_status(status, ProcessOutput::default(), &options, true).unwrap_err();
+            assert!(matches!(err, RapidsnarkError::Killed { .. }), "{err:?}");
+        }
+
+        // A SIGKILL from anyone else is taken for the OOM killer
+        let options = RapidsnarkOptions::default();
+        let err = run(&mut sh(&options, "kill -KILL $$"), &options).unwrap_err();
+        assert!(
+            matches!(err, RapidsnarkError::OutOfMemory { .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn succeeds() {
+        let options = RapidsnarkOptions::default();
+        let output = run(&mut sh(&options, "echo ok"), &options).unwrap();
+        assert_eq!(output.stdout, "ok\n");
+    }
+
+    #[test]
+    fn applies_args_and_env() {
+        let options = RapidsnarkOptions {
+            binary: "sh".into(),
+            extra_args: vec!["-c".into(), "echo $GREETING".into()],
+            env: vec![("GREETING".into(), "hello".into())],
+            ..Default::default()
+        };
+        let output = run(&mut options.command(), &options).unwrap();
+        assert_eq!(output.stdout, "hello\n");
+    }
+
+    #[test]
+    fn checks_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("rapidsnark");
+        std::fs::write(&script, "#!/bin/sh\necho rapidsnark 0.0.7\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let options = RapidsnarkOptions {
+            binary: script,
+            ..Default::default()
+        };
+        assert_eq!(options.check_binary().unwrap(), "rapidsnark 0.0.7");
+
+        let options = RapidsnarkOptions {
+            binary: dir.path().join("missing"),
+            ..Default::default()
+        };
+        assert!(options.check_binary().is_err());
+    }
+}
@@ -1,28 +1,399 @@
-use anyhow::{Result, anyhow};
-use std::{io::Cursor, path::Path};
+use anyhow::{Context, Result, anyhow};
+use sha2::{Digest as _, Sha256};
 
+use crate::{
+    prove::{input::VerifyForGuestInput, wtns::Witness},
+    registry::CircuitVersion,
+    setup::SetupDir,
+};
+use risc0_zkvm::sha::Digest;
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+/// File name of the witness in the work dir.
+pub(crate) const WITNESS_FILE: &str = "output.wtns";
+
+#[cfg(feature = "cuda")]
 pub(crate) struct CalcWitness {
     witness: Vec<wtns_file::FieldElement<32>>,
 }
 
+#[cfg(feature = "cuda")]
 impl CalcWitness {
+    pub(crate) fn decode(witness_encoded: &[u8]) -> Result<Self> {
+        let wtns_f = wtns_file::WtnsFile::read(Cursor::new(witness_encoded))?;
+        Ok(Self {
+            witness: wtns_f.witness.0,
+        })
+    }
+
     pub(crate) fn as_ptr(&self) -> *const u8 {
         self.witness.as_ptr() as *const u8
     }
 }
 
-pub fn calculate_witness(graph_path: &Path, inputs: &str) -> Result<CalcWitness> {
-    let witness_encoded = calculate_witness_encoded(graph_path, inputs)?;
-    let wtns_f = wtns_file::WtnsFile::read(Cursor::new(witness_encoded))?;
-    Ok(CalcWitness {
-        witness: wtns_f.witness.0,
-    })
+/// Deserialized `circom-witnesscalc` graph.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<circom_witnesscalc::graph::Node>,
+    signals: Vec<usize>,
+    input_mapping: circom_witnesscalc::InputSignalsInfo,
+}
+
+impl Graph {
+    fn deserialize(graph: &[u8]) -> Result<Self> {
+        let (nodes, signals, input_mapping) =
+            circom_witnesscalc::storage::deserialize_witnesscalc_graph(Cursor::new(graph))
+                .context("invalid witness graph")?;
+        Ok(Self {
+            nodes,
+            signals,
+            input_mapping,
+        })
+    }
+
+    /// Same as `circom_witnesscalc::calc_witness`, without deserializing the graph again.
+    fn evaluate(&self, inputs: &str) -> Result<Vec<u8>> {
+        let inputs = circom_witnesscalc::deserialize_inputs(inputs.as_bytes())
+            .map_err(|err| anyhow!("{err}"))?;
+        let mut inputs_buffer =
+            circom_witnesscalc::get_inputs_buffer(circom_witnesscalc::get_inputs_size(&self.nodes));
+        circom_witnesscalc::populate_inputs(&inputs, &self.input_mapping, &mut inputs_buffer);
+        let witness =
+            circom_witnesscalc::graph::evaluate(&self.nodes, &inputs_buffer, &self.signals);
+        Ok(circom_witnesscalc::wtns_from_witness(witness))
+    }
+}
+
+/// Length and modification time of a graph file, which change when a new setup replaces it.
+type GraphStamp = (u64, Option<std::time::SystemTime>);
+
+/// Witness generator for the `verify_for_guest` circuit, which keeps the deserialized witness
+/// graph in memory so that it is only parsed once per process instead of once per proof.
+pub struct WitnessGenerator {
+    graph: Graph,
+}
+
+impl std::fmt::Debug for WitnessGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WitnessGenerator")
+            .field("nodes", &self.graph.nodes.len())
+            .field("signals", &self.graph.signals.len())
+            .finish()
+    }
+}
+
+impl WitnessGenerator {
+    /// Deserializes the witness graph `graph`.
+    pub fn new(graph: &[u8]) -> Result<Self> {
+        Ok(Self {
+            graph: Graph::deserialize(graph)?,
+        })
+    }
+
+    pub fn load(graph_path: &Path) -> Result<Self> {
+        tracing::info!("loading witness graph {}", graph_path.display());
+        let graph = std::fs::read(graph_path)
+            .with_context(|| format!("failed to read {}", graph_path.display()))?;
+        Self::new(&graph).with_context(|| format!("failed to load {}", graph_path.display()))
+    }
+
+    /// Returns the generator for `graph_path`, loading the graph on first use and sharing it
+    /// between all later calls in this process for as long as the length and modification time
+    /// of the graph file stay the same.
+    pub fn shared(graph_path: &Path) -> Result<Arc<Self>> {
+        Self::shared_with(graph_path, Self::new)
+    }
+
+    fn shared_with(
+        graph_path: &Path,
+        new: impl FnOnce(&[u8]) -> Result<Self>,
+    ) -> Result<Arc<Self>> {
+        type Generators = HashMap<PathBuf, (GraphStamp, Arc<WitnessGenerator>)>;
+        static GENERATORS: OnceLock<Mutex<Generators>> = OnceLock::new();
+
+        let metadata = std::fs::metadata(graph_path)
+            .with_context(|| format!("failed to read {}", graph_path.display()))?;
+        let stamp = (metadata.len(), metadata.modified().ok());
+        let mut generators = GENERATORS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if let Some((_, generator)) = generators
+            .get(graph_path)
+            .filter(|(loaded_stamp, _)| *loaded_stamp == stamp)
+        {
+            return Ok(generator.clone());
+        }
+        tracing::info!("loading witness graph {}", graph_path.display());
+        let graph = std::fs::read(graph_path)
+            .with_context(|| format!("failed to read {}", graph_path.display()))?;
+        let generator = Arc::new(
+            new(&graph).with_context(|| format!("failed to load {}", graph_path.display()))?,
+        );
+        generators.insert(graph_path.to_path_buf(), (stamp, generator.clone()));
+        Ok(generator)
+    }
+
+    /// Computes the encoded `.wtns` witness for the circuit input JSON.
+    pub fn calculate(&self, input: &VerifyForGuestInput) -> Result<Vec<u8>> {
+        self.calculate_json(input, &input.to_json()?)
+    }
+
+    /// Computes the witness for `inputs`, the JSON of `input`. On failure, reports the input
+    /// field which most likely caused it.
+    fn calculate_json(&self, input: &VerifyForGuestInput, inputs: &str) -> Result<Vec<u8>> {
+        tracing::info!("calculate_witness");
+        let witness_encoded = self
+            .graph
+            .evaluate(inputs)
+            .map_err(|err| match input.check() {
+                Err(cause) => anyhow!("witness failure: {err}, most likely caused by {cause}"),
+                Ok(()) => anyhow!(
+                    "witness failure: {err}, the input is well formed so the seal or claim \
+                         is most likely not accepted by the circuit, compare it with the input \
+                         of a known good proof with `cargo xtask inspect-witness --expected`"
+                ),
+            })?;
+        Ok(witness_encoded)
+    }
+
+    /// Same as [WitnessGenerator::calculate], but reuses the witness from `cache` if there is
+    /// one for these inputs, and stores newly computed witnesses in it.
+    pub(crate) fn calculate_cached(
+        &self,
+        input: &VerifyForGuestInput,
+        cache: Option<&WitnessCache>,
+    ) -> Result<Vec<u8>> {
+        let inputs = input.to_json()?;
+        let Some(cache) = cache else {
+            return self.calculate_json(input, &inputs);
+        };
+
+        let key = cache.key(&inputs);
+        if let Some(witness_encoded) = cache.get(&key)? {
+            tracing::info!("reusing cached witness {key}");
+            return Ok(witness_encoded);
+        }
+        let witness_encoded = self.calculate_json(input, &inputs)?;
+        cache.insert(&key, &witness_encoded)?;
+        Ok(witness_encoded)
+    }
+
+    /// Same as [WitnessGenerator::calculate_cached], and checks that the public output of the
+    /// witness is `claim_digest` before it is proven.
+    pub(crate) fn calculate_checked(
+        &self,
+        input: &VerifyForGuestInput,
+        claim_digest: &Digest,
+        cache: Option<&WitnessCache>,
+    ) -> Result<Vec<u8>> {
+        let witness_encoded = self.calculate_cached(input, cache)?;
+        Witness::decode(&witness_encoded)?.check_public_output(claim_digest)?;
+        Ok(witness_encoded)
+    }
+
+    /// Computes the decoded witness, as expected by the CUDA prover.
+    #[cfg(feature = "cuda")]
+    pub(crate) fn calculate_decoded(
+        &self,
+        input: &VerifyForGuestInput,
+        claim_digest: &Digest,
+        cache: Option<&WitnessCache>,
+    ) -> Result<CalcWitness> {
+        CalcWitness::decode(&self.calculate_checked(input, claim_digest, cache)?)
+    }
+}
+
+/// Directory of `.wtns` files keyed by the setup and the SHA-256 of the circuit input JSON they
+/// were computed from, so that a failed proving attempt, or one with another backend, doesn't
+/// recompute the witness.
+///
+/// Entries are kept after a successful proof, evict them with [WitnessCache::remove].
+#[derive(Clone, Debug)]
+pub struct WitnessCache {
+    dir: PathBuf,
+    setup_fingerprint: String,
+}
+
+impl WitnessCache {
+    /// Opens the cache in `dir` for the witnesses of the setup with `setup_fingerprint`, see
+    /// [SetupManifest::fingerprint](crate::setup::SetupManifest::fingerprint).
+    pub fn new(dir: impl Into<PathBuf>, setup_fingerprint: &str) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create witness cache {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            setup_fingerprint: setup_fingerprint.to_string(),
+        })
+    }
+
+    /// Opens the cache at `SHRINK_BVM2_WITNESS_CACHE_DIR` for `setup_dir`, if set.
+    pub fn from_env(setup_dir: &SetupDir) -> Result<Option<Self>> {
+        let Some(dir) = std::env::var_os("SHRINK_BVM2_WITNESS_CACHE_DIR") else {
+            return Ok(None);
+        };
+        let fingerprint = setup_dir.read_manifest()?.fingerprint();
+        Self::new(dir, &fingerprint).map(Some)
+    }
+
+    /// Cache key of the witness for the given circuit input JSON, see [VerifyForGuestInput::to_json].
+    pub fn key(&self, inputs: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.setup_fingerprint.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(inputs.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Cache key of the witness for shrinking `p254_receipt` with `journal` for `version`.
+    pub fn key_for(
+        &self,
+        p254_receipt: &risc0_zkvm::SuccinctReceipt<risc0_zkvm::ReceiptClaim>,
+        journal: &[u8],
+        version: &CircuitVersion,
+    ) -> Result<String> {
+        let input = VerifyForGuestInput::for_version(p254_receipt, journal, version)?;
+        Ok(self.key(&input.to_json()?))
+    }
+
+    pub fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.wtns"))
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(key);
+        match std::fs::read(&path) {
+            Ok(witness_encoded) => Ok(Some(witness_encoded)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Stores a witness. The file is written atomically, so a crash never leaves a truncated
+    /// witness behind.
+    pub fn insert(&self, key: &str, witness_encoded: &[u8]) -> Result<PathBuf> {
+        let path = self.path(key);
+        let tmp_path = self.dir.join(format!("{key}.wtns.tmp"));
+        std::fs::write(&tmp_path, witness_encoded)
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.path(key)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copies the cached witness for `key` to `dest`.
+    pub fn export(&self, key: &str, dest: &Path) -> Result<()> {
+        let witness_encoded = self
+            .get(key)?
+            .with_context(|| format!("no cached witness for {key}"))?;
+        std::fs::write(dest, witness_encoded)
+            .with_context(|| format!("failed to write {}", dest.display()))
+    }
+
+    /// Adds the witness at `src` to the cache under `key`, after checking that it is a valid
+    /// `.wtns` file.
+    pub fn import(&self, key: &str, src: &Path) -> Result<()> {
+        let witness_encoded =
+            std::fs::read(src).with_context(|| format!("failed to read {}", src.display()))?;
+        wtns_file::WtnsFile::<32>::read(Cursor::new(&witness_encoded))
+            .with_context(|| format!("{} is not a valid witness", src.display()))?;
+        self.insert(key, &witness_encoded)?;
+        Ok(())
+    }
 }
 
-pub fn calculate_witness_encoded(graph_path: &Path, inputs: &str) -> Result<Vec<u8>> {
-    tracing::info!("calculate_witness");
-    let graph = std::fs::read(graph_path)?;
-    let witness_encoded = circom_witnesscalc::calc_witness(inputs, &graph)
-        .map_err(|err| anyhow!("witness failure: {err}"))?;
-    Ok(witness_encoded)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = WitnessCache::new(dir.path().join("cache"), "setup").unwrap();
+        let key = cache.key(r#"{"iop":[]}"#);
+        assert_ne!(key, cache.key(r#"{"iop":["1"]}"#));
+        // Witnesses of another setup are never reused
+        let other_setup = WitnessCache::new(dir.path().join("cache"), "other setup").unwrap();
+        assert_ne!(key, other_setup.key(r#"{"iop":[]}"#));
+
+        assert!(cache.get(&key).unwrap().is_none());
+        cache.insert(&key, b"witness").unwrap();
+        assert_eq!(cache.get(&key).unwrap().unwrap(), b"witness");
+
+        let exported = dir.path().join("exported.wtns");
+        cache.export(&key, &exported).unwrap();
+        assert_eq!(std::fs::read(&exported).unwrap(), b"witness");
+
+        // Only valid witness files can be imported
+        assert!(cache.import(&key, &exported).is_err());
+
+        cache.remove(&key).unwrap();
+        assert!(cache.get(&key).unwrap().is_none());
+        cache.remove(&key).unwrap();
+    }
+
+    #[test]
+    fn cached_witness_is_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = WitnessCache::new(dir.path(), "setup").unwrap();
+        let input = VerifyForGuestInput {
+            iop: Vec::new(),
+            journal_digest_bits: Vec::new(),
+            pre_state_digest_bits: Vec::new(),
+            post_state_digest_bits: Vec::new(),
+            id_bn254_fr_bits: Vec::new(),
+            control_root: Default::default(),
+        };
+        cache
+            .insert(&cache.key(&input.to_json().unwrap()), b"witness")
+            .unwrap();
+
+        // The graph is empty, so this only succeeds if the cached witness is used
+        let generator = WitnessGenerator {
+            graph: Graph::default(),
+        };
+        assert_eq!(
+            generator.calculate_cached(&input, Some(&cache)).unwrap(),
+            b"witness"
+        );
+    }
+
+    #[test]
+    fn shared_generator_is_reloaded_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let graph_path = dir.path().join("graph.bin");
+        let loads = std::cell::Cell::new(0);
+        let shared = || {
+            WitnessGenerator::shared_with(&graph_path, |_| {
+                loads.set(loads.get() + 1);
+                Ok(WitnessGenerator {
+                    graph: Graph::default(),
+                })
+            })
+        };
+        assert!(shared().is_err());
+
+        std::fs::write(&graph_path, b"graph").unwrap();
+        let generator = shared().unwrap();
+        assert!(Arc::ptr_eq(&generator, &shared().unwrap()));
+        assert_eq!(loads.get(), 1);
+
+        // A new setup replaces the graph, which must not keep using the old one
+        std::fs::write(&graph_path, b"new graph").unwrap();
+        assert!(!Arc::ptr_eq(&generator, &shared().unwrap()));
+        assert_eq!(loads.get(), 2);
+    }
 }
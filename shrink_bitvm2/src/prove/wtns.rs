@@ -0,0 +1,276 @@
+//! Decoder of the iden3 `.wtns` witness format produced by the witness generator.
+
+use anyhow::{Context, Result, bail, ensure};
+use num_bigint::BigUint;
+use risc0_zkvm::sha::Digest;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"wtns";
+const HEADER_SECTION: u32 = 1;
+const WITNESS_SECTION: u32 = 2;
+
+/// Index of the first public signal, signal 0 is the constant 1.
+const PUBLIC_OUTPUT_INDEX: usize = 1;
+
+/// A decoded witness, with its signals in little-endian bytes.
+#[derive(Clone, Debug)]
+pub struct Witness<'a> {
+    field_size: usize,
+    prime: BigUint,
+    signals: &'a [u8],
+}
+
+impl<'a> Witness<'a> {
+    pub fn decode(witness_encoded: &'a [u8]) -> Result<Self> {
+        let mut reader = Reader(witness_encoded);
+        ensure!(reader.take(4)? == MAGIC, "not a .wtns file");
+        let _version = reader.u32()?;
+        let sections = reader.u32()?;
+
+        let mut header = None;
+        let mut signals = None;
+        for _ in 0..sections {
+            let section_type = reader.u32()?;
+            let size = usize::try_from(reader.u64()?)?;
+            let mut section = Reader(reader.take(size)?);
+            match section_type {
+                HEADER_SECTION => {
+                    let field_size = section.u32()? as usize;
+                    let prime = BigUint::from_bytes_le(section.take(field_size)?);
+                    let count = section.u32()? as usize;
+                    header = Some((field_size, prime, count));
+                }
+                WITNESS_SECTION => signals = Some(section.0),
+                _ => {}
+            }
+        }
+
+        let (field_size, prime, count) = header.context(".wtns file has no header section")?;
+        ensure!(field_size > 0, ".wtns file has an empty field size");
+        let signals = signals.context(".wtns file has no witness section")?;
+        ensure!(
+            signals.len() == field_size * count,
+            ".wtns witness section is {} bytes, expected {count} signals of {field_size} bytes",
+            signals.len()
+        );
+        Ok(Self {
+            field_size,
+            prime,
+            signals,
+        })
+    }
+
+    /// Number of signals, including the constant signal 0.
+    pub fn len(&self) -> usize {
+        self.signals.len() / self.field_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signals.is_empty()
+    }
+
+    pub fn prime(&self) -> &BigUint {
+        &self.prime
+    }
+
+    pub fn signal(&self, index: usize) -> Option<BigUint> {
+        let start = index.checked_mul(self.field_size)?;
+        let bytes = self.signals.get(start..start + self.field_size)?;
+        Some(BigUint::from_bytes_le(bytes))
+    }
+
+    /// Value of the signal `name` of `symbols`.
+    pub fn named(&self, symbols: &Symbols, name: &str) -> Result<BigUint> {
+        let index = symbols
+            .index(name)
+            .with_context(|| format!("unknown signal {name}"))?;
+        self.signal(index)
+            .with_context(|| format!("signal {name} is out of the witness"))
+    }
+
+    /// The public output of the `verify_for_guest` circuit, the BitVM2 claim digest.
+    pub fn public_output(&self) -> Result<BigUint> {
+        self.signal(PUBLIC_OUTPUT_INDEX)
+            .context("witness has no public output")
+    }
+
+    /// Checks that the public output is `claim_digest`, so that a witness of the wrong claim is
+    /// rejected before spending minutes proving it.
+    pub fn check_public_output(&self, claim_digest: &Digest) -> Result<()> {
+        // The claim digest is the big-endian encoding of the public input, see
+        // [crate::verify::verify_integrity]
+        let expected = BigUint::from_bytes_be(claim_digest.as_bytes());
+        let actual = self.public_output()?;
+        if actual != expected {
+            bail!(
+                "witness public output {actual:#x} does not match the BitVM2 claim digest \
+                 {claim_digest} ({expected:#x}), the circuit input doesn't encode the expected \
+                 image ID, journal, control root or BN254 control ID"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Signal names of a circom `.sym` file, whose lines are `label,witness index,component,name`.
+/// Signals eliminated by the compiler have the witness index -1 and are skipped.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    signals: Vec<(String, usize)>,
+}
+
+impl Symbols {
+    pub fn parse(sym: &str) -> Result<Self> {
+        let mut signals = Vec::new();
+        for (line_number, line) in sym.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(4, ',');
+            let (Some(_label), Some(index), Some(_component), Some(name)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                bail!("invalid .sym line {}: {line}", line_number + 1);
+            };
+            let index: i64 = index
+                .parse()
+                .with_context(|| format!("invalid .sym line {}: {line}", line_number + 1))?;
+            if let Ok(index) = usize::try_from(index) {
+                signals.push((name.to_string(), index));
+            }
+        }
+        Ok(Self { signals })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let sym = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&sym)
+    }
+
+    /// Witness index of the signal `name`, e.g. `main.control_root[0]`.
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.signals
+            .iter()
+            .find(|(signal, _)| signal == name)
+            .map(|(_, index)| *index)
+    }
+
+    /// Names of the signals stored at witness `index`.
+    pub fn names(&self, index: usize) -> impl Iterator<Item = &str> {
+        self.signals
+            .iter()
+            .filter(move |(_, signal_index)| *signal_index == index)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Signals whose name starts with `prefix`, with their witness index.
+    pub fn matching<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, usize)> {
+        self.signals
+            .iter()
+            .filter(move |(name, _)| name.starts_with(prefix))
+            .map(|(name, index)| (name.as_str(), *index))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(self.0.len() >= len, "truncated .wtns file");
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc0_zkvm::sha::Digestible;
+
+    /// Encodes `signals` as a `.wtns` file with 32-byte field elements.
+    fn encode(signals: &[BigUint]) -> Vec<u8> {
+        let element = |value: &BigUint| {
+            let mut bytes = value.to_bytes_le();
+            bytes.resize(32, 0);
+            bytes
+        };
+        let prime = BigUint::parse_bytes(
+            b"21888242871839275222246405745257275088548364400416034343698204186575808495617",
+            10,
+        )
+        .unwrap();
+
+        let mut header = 32u32.to_le_bytes().to_vec();
+        header.extend(element(&prime));
+        header.extend((signals.len() as u32).to_le_bytes());
+        let witness: Vec<u8> = signals.iter().flat_map(element).collect();
+
+        let mut out = MAGIC.to_vec();
+        out.extend(2u32.to_le_bytes());
+        out.extend(2u32.to_le_bytes());
+        for (section_type, section) in [(HEADER_SECTION, header), (WITNESS_SECTION, witness)] {
+            out.extend(section_type.to_le_bytes());
+            out.extend((section.len() as u64).to_le_bytes());
+            out.extend(section);
+        }
+        out
+    }
+
+    #[test]
+    fn decode() {
+        let encoded = encode(&[1u8.into(), 42u8.into(), BigUint::from(1u8) << 200u32]);
+        let witness = Witness::decode(&encoded).unwrap();
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness.signal(0), Some(1u8.into()));
+        assert_eq!(witness.public_output().unwrap(), 42u8.into());
+        assert_eq!(witness.signal(2), Some(BigUint::from(1u8) << 200u32));
+        assert_eq!(witness.signal(3), None);
+
+        assert!(Witness::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(Witness::decode(b"nope").is_err());
+    }
+
+    #[test]
+    fn symbols() {
+        let symbols = Symbols::parse(
+            "1,1,0,main.out\n2,2,0,main.control_root[0]\n3,3,0,main.control_root[1]\n4,-1,1,main.c.x\n",
+        )
+        .unwrap();
+        assert_eq!(symbols.index("main.control_root[1]"), Some(3));
+        assert_eq!(symbols.index("main.c.x"), None);
+        assert_eq!(symbols.names(1).collect::<Vec<_>>(), ["main.out"]);
+        assert_eq!(symbols.matching("main.control_root").count(), 2);
+        assert!(Symbols::parse("1,x,0,main.out").is_err());
+
+        let encoded = encode(&[1u8.into(), 2u8.into(), 3u8.into(), 4u8.into()]);
+        let witness = Witness::decode(&encoded).unwrap();
+        assert_eq!(
+            witness.named(&symbols, "main.control_root[0]").unwrap(),
+            3u8.into()
+        );
+        assert!(witness.named(&symbols, "main.c.x").is_err());
+    }
+
+    #[test]
+    fn check_public_output() {
+        let claim_digest = crate::ShrinkBitvm2ReceiptClaim::ok([1u32; 8], vec![3u8; 32]).digest();
+        let public_output = BigUint::from_bytes_be(claim_digest.as_bytes());
+        let encoded = encode(&[1u8.into(), public_output]);
+        let witness = Witness::decode(&encoded).unwrap();
+        witness.check_public_output(&claim_digest).unwrap();
+
+        let other = crate::ShrinkBitvm2ReceiptClaim::ok([1u32; 8], vec![4u8; 32]).digest();
+        let err = witness.check_public_output(&other).unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+    }
+}
@@ -0,0 +1,201 @@
+//! A [Prover] which proves guests straight to BitVM2 receipts.
+
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, ensure};
+use risc0_zkvm::{
+    Digest, ExecutorEnv, ProveInfo, Prover, ProverOpts, Receipt, ReceiptKind as R0ReceiptKind,
+    VerifierContext, default_prover,
+};
+
+use crate::registry::CircuitVersion;
+
+/// Time spent in each stage of a [Bitvm2Prover] proof.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageTimings {
+    /// Proving the guest to a succinct receipt.
+    pub succinct: Duration,
+    pub identity_p254: Duration,
+    /// Witness generation, Groth16 proving and verification of the proof.
+    pub shrink_wrap: Duration,
+}
+
+impl StageTimings {
+    pub fn total(&self) -> Duration {
+        self.succinct + self.identity_p254 + self.shrink_wrap
+    }
+}
+
+/// Output of [Bitvm2Prover::prove_with_timings].
+pub struct Bitvm2ProveInfo {
+    /// The BitVM2 receipt and the stats of the proven session.
+    pub info: ProveInfo,
+    pub timings: StageTimings,
+}
+
+/// A [Prover] whose receipts are BitVM2 Groth16 receipts. Guests are proven to a succinct
+/// receipt with another prover, which is then shrunk as by [crate::succinct_to_bitvm2].
+pub struct Bitvm2Prover {
+    succinct_prover: Rc<dyn Prover>,
+    control: CircuitVersion,
+}
+
+impl Bitvm2Prover {
+    pub fn new(succinct_prover: Rc<dyn Prover>) -> Self {
+        Self {
+            succinct_prover,
+            control: CircuitVersion::current(),
+        }
+    }
+
+    /// See [crate::succinct_to_bitvm2_with_control].
+    pub fn with_control(mut self, control_root: Digest, bn254_control_id: Digest) -> Self {
+        self.control = CircuitVersion::with_control(control_root, bn254_control_id);
+        self
+    }
+
+    /// Same as [Prover::prove_with_ctx], also returning the time spent in each stage.
+    ///
+    /// `opts` must request [R0ReceiptKind::Groth16] receipts, and `ctx` is forwarded to the
+    /// succinct prover. Its succinct verifier parameters, if set, must have the control root of
+    /// this prover.
+    pub fn prove_with_timings(
+        &self,
+        env: ExecutorEnv<'_>,
+        ctx: &VerifierContext,
+        elf: &[u8],
+        opts: &ProverOpts,
+    ) -> Result<Bitvm2ProveInfo> {
+        check_receipt_kind(opts)?;
+        if let Some(params) = &ctx.succinct_verifier_parameters {
+            ensure!(
+                params.control_root == self.control.control_root,
+                "verifier context control root {} does not match the BitVM2 control root {}",
+                params.control_root,
+                self.control.control_root
+            );
+        }
+        let mut timings = StageTimings::default();
+        let start = Instant::now();
+        let mut info = self.succinct_prover.prove_with_ctx(
+            env,
+            ctx,
+            elf,
+            &opts.clone().with_receipt_kind(R0ReceiptKind::Succinct),
+        )?;
+        timings.succinct = start.elapsed();
+
+        info.receipt = crate::run_succinct_to_bitvm2(
+            info.receipt.inner.succinct()?,
+            &info.receipt.journal.bytes,
+            &self.control,
+            &mut timings,
+        )?;
+        tracing::info!(?timings, "proved BitVM2 receipt");
+        Ok(Bitvm2ProveInfo { info, timings })
+    }
+}
+
+impl Default for Bitvm2Prover {
+    fn default() -> Self {
+        Self::new(default_prover())
+    }
+}
+
+impl Prover for Bitvm2Prover {
+    fn get_name(&self) -> String {
+        format!("bitvm2({})", self.succinct_prover.get_name())
+    }
+
+    fn prove_with_ctx(
+        &self,
+        env: ExecutorEnv<'_>,
+        ctx: &VerifierContext,
+        elf: &[u8],
+        opts: &ProverOpts,
+    ) -> Result<ProveInfo> {
+        Ok(self.prove_with_timings(env, ctx, elf, opts)?.info)
+    }
+
+    fn compress(&self, opts: &ProverOpts, receipt: &Receipt) -> Result<Receipt> {
+        check_receipt_kind(opts)?;
+        let succinct_receipt = self.succinct_prover.compress(
+            &opts.clone().with_receipt_kind(R0ReceiptKind::Succinct),
+            receipt,
+        )?;
+        crate::run_succinct_to_bitvm2(
+            succinct_receipt.inner.succinct()?,
+            &succinct_receipt.journal.bytes,
+            &self.control,
+            &mut StageTimings::default(),
+        )
+    }
+}
+
+/// BitVM2 receipts are Groth16 receipts, other receipt kinds can't be produced.
+fn check_receipt_kind(opts: &ProverOpts) -> Result<()> {
+    ensure!(
+        opts.receipt_kind == R0ReceiptKind::Groth16,
+        "Bitvm2Prover only produces Groth16 receipts, got {:?}",
+        opts.receipt_kind
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guest::{ECHO_ELF, ECHO_ID};
+
+    #[test]
+    fn prove() {
+        let input = [3u8; 32];
+        let env = ExecutorEnv::builder().write_slice(&input).build().unwrap();
+        let Bitvm2ProveInfo { info, timings } = Bitvm2Prover::default()
+            .prove_with_timings(
+                env,
+                &VerifierContext::default(),
+                ECHO_ELF,
+                &ProverOpts::groth16(),
+            )
+            .unwrap();
+
+        crate::verify::verify_receipt(&info.receipt, ECHO_ID).unwrap();
+        assert!(info.receipt.inner.groth16().is_ok());
+        assert_eq!(info.receipt.journal.bytes, input);
+        assert!(info.stats.total_cycles > 0);
+        assert!(timings.succinct > Duration::ZERO && timings.shrink_wrap > Duration::ZERO);
+    }
+
+    #[test]
+    fn rejects_unsupported_opts() {
+        let env = || {
+            ExecutorEnv::builder()
+                .write_slice(&[3u8; 32])
+                .build()
+                .unwrap()
+        };
+        let prover = Bitvm2Prover::default();
+        let ctx = VerifierContext::default();
+        for opts in [ProverOpts::default(), ProverOpts::succinct()] {
+            let err = prover
+                .prove_with_ctx(env(), &ctx, ECHO_ELF, &opts)
+                .unwrap_err();
+            assert!(err.to_string().contains("Groth16"), "{err:?}");
+        }
+
+        let ctx = VerifierContext::default().with_succinct_verifier_parameters(
+            risc0_zkvm::SuccinctReceiptVerifierParameters {
+                control_root: Digest::ZERO,
+                ..Default::default()
+            },
+        );
+        let err = prover
+            .prove_with_ctx(env(), &ctx, ECHO_ELF, &ProverOpts::groth16())
+            .unwrap_err();
+        assert!(err.to_string().contains("control root"), "{err:?}");
+    }
+}
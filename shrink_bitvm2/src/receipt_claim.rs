@@ -1,5 +1,7 @@
 use risc0_zkvm::{Digest, MaybePruned, SystemState, sha::Digestible};
 
+use crate::registry::CircuitVersion;
+
 use serde::Serialize;
 
 #[derive(Clone, Debug, Serialize)]
@@ -17,8 +19,31 @@ impl ShrinkBitvm2ReceiptClaim {
         image_id: impl Into<Digest>,
         journal: impl Into<Vec<u8>>,
     ) -> ShrinkBitvm2ReceiptClaim {
-        let verifier_params = risc0_zkvm::SuccinctReceiptVerifierParameters::default();
-        let control_root = verifier_params.control_root;
+        Self::for_version(image_id, journal, &CircuitVersion::current())
+    }
+
+    /// Claim of a proof produced with the circuit `version`.
+    pub fn for_version(
+        image_id: impl Into<Digest>,
+        journal: impl Into<Vec<u8>>,
+        version: &CircuitVersion,
+    ) -> ShrinkBitvm2ReceiptClaim {
+        Self::with_control(
+            image_id,
+            journal,
+            version.control_root,
+            version.bn254_control_id,
+        )
+    }
+
+    /// Claim of a proof of a succinct receipt verified against `control_root`, shrunk with the
+    /// `identity_p254` program of `bn254_control_id`.
+    pub fn with_control(
+        image_id: impl Into<Digest>,
+        journal: impl Into<Vec<u8>>,
+        control_root: Digest,
+        bn254_control_id: Digest,
+    ) -> ShrinkBitvm2ReceiptClaim {
         Self {
             control_root,
             pre: MaybePruned::Pruned(image_id.into()),
@@ -26,10 +51,11 @@ impl ShrinkBitvm2ReceiptClaim {
                 pc: 0,
                 merkle_root: Digest::ZERO,
             }),
-            control_id: crate::BN254_IDENTITY_CONTROL_ID,
+            control_id: bn254_control_id,
             journal: journal.into(),
         }
     }
+
     pub fn claim_digest(&self) -> Digest {
         self.digest()
     }
@@ -64,3 +90,34 @@ impl Digestible for ShrinkBitvm2ReceiptClaim {
         digest_bytes.into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_control() {
+        let current = CircuitVersion::current();
+        let claim = ShrinkBitvm2ReceiptClaim::with_control(
+            [1u32; 8],
+            vec![3u8; 32],
+            current.control_root,
+            current.bn254_control_id,
+        );
+        assert_eq!(
+            claim.digest(),
+            ShrinkBitvm2ReceiptClaim::ok([1u32; 8], vec![3u8; 32]).digest()
+        );
+
+        let claim = ShrinkBitvm2ReceiptClaim::with_control(
+            [1u32; 8],
+            vec![3u8; 32],
+            Digest::from([1u32; 8]),
+            current.bn254_control_id,
+        );
+        assert_ne!(
+            claim.digest(),
+            ShrinkBitvm2ReceiptClaim::ok([1u32; 8], vec![3u8; 32]).digest()
+        );
+    }
+}
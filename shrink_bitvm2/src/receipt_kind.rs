@@ -0,0 +1,160 @@
+use anyhow::{Context, Result, bail};
+use risc0_groth16::Seal as Groth16Seal;
+use risc0_zkvm::{
+    Digest, Groth16Receipt, Groth16ReceiptVerifierParameters, InnerReceipt, Receipt, ReceiptClaim,
+    sha::Digestible,
+};
+
+use crate::{
+    registry::{CircuitVersion, VersionRegistry},
+    verify,
+};
+
+/// Typed view over a receipt produced by [crate::finalize].
+#[derive(Clone, Debug)]
+pub struct Bitvm2ReceiptView<'a> {
+    pub seal: Groth16Seal,
+    pub image_id: Digest,
+    pub journal: &'a [u8],
+    pub verifier_parameters: Digest,
+    /// Circuit version registered for `verifier_parameters`.
+    pub version: CircuitVersion,
+}
+
+impl Bitvm2ReceiptView<'_> {
+    pub fn verify(&self) -> Result<()> {
+        verify::verify_proof_with_version(
+            &self.seal,
+            self.image_id,
+            self.journal.to_vec(),
+            &self.version,
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ReceiptKind<'a> {
+    /// A BitVM2 Groth16 receipt, whose seal commits to a [crate::ShrinkBitvm2ReceiptClaim].
+    Bitvm2(Bitvm2ReceiptView<'a>),
+    /// A standard RISC Zero Groth16 receipt.
+    Groth16(&'a Groth16Receipt<ReceiptClaim>),
+}
+
+impl<'a> ReceiptKind<'a> {
+    /// Classifies a receipt by the verifier parameters digest of its Groth16 inner receipt.
+    ///
+    /// Returns an error for non-Groth16 receipts and for unrecognized verifier parameters.
+    pub fn classify(receipt: &'a Receipt) -> Result<Self> {
+        Self::classify_with_registry(receipt, &VersionRegistry::builtin())
+    }
+
+    /// Same as [ReceiptKind::classify], recognizing the BitVM2 versions of `registry`. Receipts
+    /// of revoked versions are rejected.
+    pub fn classify_with_registry(
+        receipt: &'a Receipt,
+        registry: &VersionRegistry,
+    ) -> Result<Self> {
+        let InnerReceipt::Groth16(inner) = &receipt.inner else {
+            bail!("unsupported receipt kind: expected a Groth16 receipt");
+        };
+
+        if registry.get(&inner.verifier_parameters).is_some() {
+            let version = registry.lookup(&inner.verifier_parameters)?.clone();
+            let image_id = inner
+                .claim
+                .as_value()
+                .context("BitVM2 receipt claim is pruned")?
+                .pre
+                .digest();
+            let seal = Groth16Seal::from_vec(&inner.seal)?;
+            return Ok(Self::Bitvm2(Bitvm2ReceiptView {
+                seal,
+                image_id,
+                journal: &receipt.journal.bytes,
+                verifier_parameters: inner.verifier_parameters,
+                version,
+            }));
+        }
+
+        if inner.verifier_parameters == Groth16ReceiptVerifierParameters::default().digest() {
+            return Ok(Self::Groth16(inner));
+        }
+
+        bail!(
+            "unknown Groth16 verifier parameters: {}",
+            inner.verifier_parameters
+        )
+    }
+
+    pub fn is_bitvm2(&self) -> bool {
+        matches!(self, Self::Bitvm2(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc0_zkvm::FakeReceipt;
+
+    const IMAGE_ID: [u32; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn groth16_receipt(verifier_parameters: Digest) -> Receipt {
+        let journal = vec![3u8; 32];
+        let claim = ReceiptClaim::ok(IMAGE_ID, journal.clone());
+        let inner = Groth16Receipt::new(vec![0u8; 256], claim.into(), verifier_parameters);
+        Receipt::new(InnerReceipt::Groth16(inner), journal)
+    }
+
+    #[test]
+    fn classify_bitvm2() {
+        let receipt = groth16_receipt(verify::verifier_parameters_digest());
+        let ReceiptKind::Bitvm2(view) = ReceiptKind::classify(&receipt).unwrap() else {
+            panic!("expected a BitVM2 receipt");
+        };
+        assert_eq!(view.image_id, Digest::from(IMAGE_ID));
+        assert_eq!(view.journal, &[3u8; 32]);
+        assert_eq!(view.seal.to_vec(), vec![0u8; 256]);
+    }
+
+    #[test]
+    fn classify_with_registry() {
+        let old_version = CircuitVersion {
+            control_root: Digest::from([1u32; 8]),
+            ..CircuitVersion::current()
+        };
+        let mut registry = VersionRegistry::builtin();
+        let digest = registry.insert(old_version.clone()).unwrap();
+
+        let receipt = groth16_receipt(old_version.verifier_parameters_digest());
+        assert!(ReceiptKind::classify(&receipt).is_err());
+        let ReceiptKind::Bitvm2(view) =
+            ReceiptKind::classify_with_registry(&receipt, &registry).unwrap()
+        else {
+            panic!("expected a BitVM2 receipt");
+        };
+        assert_eq!(view.version, old_version);
+
+        registry.revoke(&digest).unwrap();
+        let err = ReceiptKind::classify_with_registry(&receipt, &registry).unwrap_err();
+        assert!(err.to_string().contains("revoked"));
+    }
+
+    #[test]
+    fn classify_groth16() {
+        let receipt = groth16_receipt(Groth16ReceiptVerifierParameters::default().digest());
+        assert!(matches!(
+            ReceiptKind::classify(&receipt).unwrap(),
+            ReceiptKind::Groth16(_)
+        ));
+    }
+
+    #[test]
+    fn classify_unknown() {
+        let receipt = groth16_receipt(Digest::ZERO);
+        assert!(ReceiptKind::classify(&receipt).is_err());
+
+        let claim = ReceiptClaim::ok(IMAGE_ID, vec![3u8; 32]);
+        let receipt = Receipt::new(InnerReceipt::Fake(FakeReceipt::new(claim)), vec![3u8; 32]);
+        assert!(ReceiptKind::classify(&receipt).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Versions of the BitVM2 circuit and verifier parameters, so that receipts produced with an
+//! older circuit or risc0 control root can still be verified with the parameters they were
+//! produced with.
+
+use anyhow::{Context, Result, bail, ensure};
+use risc0_zkp::core::hash::poseidon_254::Poseidon254HashSuite;
+use risc0_zkvm::{
+    Digest, ReceiptClaim, SuccinctReceipt, SuccinctReceiptVerifierParameters, VerifierContext,
+    sha::Digestible,
+};
+
+use crate::{
+    BN254_IDENTITY_CONTROL_ID,
+    bundle::CIRCUIT_COMMIT,
+    verifier_parameters::{Bitvm2ReceiptVerifierParameters, CLAIM_DIGEST_VERSION},
+    verify::Bitvm2VerifyingKey,
+};
+
+/// Hash function of the receipts produced by `identity_p254`.
+pub(crate) const P254_HASHFN: &str = "poseidon_254";
+
+/// Everything a BitVM2 proof of one circuit version depends on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitVersion {
+    pub verifying_key: Bitvm2VerifyingKey,
+    /// Control root of the succinct receipts the circuit verifies.
+    pub control_root: Digest,
+    /// Control ID of the `identity_p254` program.
+    pub bn254_control_id: Digest,
+    /// Commit of `risc0-to-bitvm2` the circuit is built from.
+    pub circuit_commit: String,
+    /// Revoked versions are known, but their proofs are rejected.
+    pub revoked: bool,
+}
+
+impl CircuitVersion {
+    /// Version of the circuit and risc0 this crate is built with.
+    pub fn current() -> Self {
+        Self {
+            verifying_key: Bitvm2VerifyingKey::Production,
+            control_root: SuccinctReceiptVerifierParameters::default().control_root,
+            bn254_control_id: BN254_IDENTITY_CONTROL_ID,
+            circuit_commit: CIRCUIT_COMMIT.to_string(),
+            revoked: false,
+        }
+    }
+
+    /// Current circuit, verifying succinct receipts against `control_root` and shrinking them
+    /// with the `identity_p254` program of `bn254_control_id`.
+    pub fn with_control(control_root: Digest, bn254_control_id: Digest) -> Self {
+        Self {
+            control_root,
+            bn254_control_id,
+            ..Self::current()
+        }
+    }
+
+    /// Checks that `succinct_receipt` verifies against the control root of this version.
+    pub fn check_succinct_receipt(
+        &self,
+        succinct_receipt: &SuccinctReceipt<ReceiptClaim>,
+    ) -> Result<()> {
+        let params = SuccinctReceiptVerifierParameters {
+            control_root: self.control_root,
+            ..Default::default()
+        };
+        succinct_receipt
+            .verify_integrity_with_context(
+                &VerifierContext::default().with_succinct_verifier_parameters(params),
+            )
+            .with_context(|| {
+                format!(
+                    "succinct receipt does not verify against control root {}",
+                    self.control_root
+                )
+            })
+    }
+
+    /// Checks that `p254_receipt` is a valid receipt of the `identity_p254` program of this
+    /// version: its seal verifies, its control ID is [CircuitVersion::bn254_control_id] and it
+    /// verified a receipt of [CircuitVersion::control_root]. The circuit only fails on an
+    /// invalid p254 receipt after minutes of witness generation.
+    pub fn check_p254_receipt(&self, p254_receipt: &SuccinctReceipt<ReceiptClaim>) -> Result<()> {
+        ensure!(
+            p254_receipt.hashfn == P254_HASHFN,
+            "p254 receipt uses the hash function {}, expected {P254_HASHFN}",
+            p254_receipt.hashfn
+        );
+        ensure!(
+            p254_receipt.control_id == self.bn254_control_id,
+            "p254 receipt control ID {} does not match the BN254 control ID {}",
+            p254_receipt.control_id,
+            self.bn254_control_id
+        );
+
+        let suite = Poseidon254HashSuite::new_suite();
+        let params = SuccinctReceiptVerifierParameters {
+            // The seal must have been produced by the BN254 control ID through the inclusion
+            // proof of the receipt
+            control_root: p254_receipt
+                .control_inclusion_proof
+                .root(&self.bn254_control_id, suite.hashfn.as_ref()),
+            inner_control_root: Some(self.control_root),
+            ..Default::default()
+        };
+        let mut suites = VerifierContext::default_hash_suites();
+        suites.insert(P254_HASHFN.to_string(), suite);
+        p254_receipt
+            .verify_integrity_with_context(
+                &VerifierContext::default()
+                    .with_suites(suites)
+                    .with_succinct_verifier_parameters(params),
+            )
+            .with_context(|| {
+                format!(
+                    "invalid p254 receipt for BN254 control ID {} and control root {}",
+                    self.bn254_control_id, self.control_root
+                )
+            })
+    }
+
+    pub fn verifier_parameters(&self) -> Bitvm2ReceiptVerifierParameters {
+        Bitvm2ReceiptVerifierParameters {
+            verifying_key: self.verifying_key.clone(),
+            control_root: self.control_root,
+            bn254_control_id: self.bn254_control_id,
+            claim_digest_version: CLAIM_DIGEST_VERSION,
+        }
+    }
+
+    /// Digest of [CircuitVersion::verifier_parameters], the `verifier_parameters` of the receipts
+    /// of this version.
+    pub fn verifier_parameters_digest(&self) -> Digest {
+        self.verifier_parameters().digest()
+    }
+}
+
+/// Known [CircuitVersion]s, keyed by their verifier parameters digest.
+#[derive(Clone, Debug)]
+pub struct VersionRegistry {
+    versions: Vec<(Digest, CircuitVersion)>,
+}
+
+impl VersionRegistry {
+    pub fn empty() -> Self {
+        Self {
+            versions: Vec::new(),
+        }
+    }
+
+    /// Versions released with this crate. Add historical versions here when the circuit or the
+    /// risc0 control root changes, instead of replacing the current one.
+    pub fn builtin() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .insert(CircuitVersion::current())
+            .expect("builtin versions must be unique");
+        registry
+    }
+
+    /// Registers `version`, returning its [CircuitVersion::verifier_parameters_digest].
+    pub fn insert(&mut self, version: CircuitVersion) -> Result<Digest> {
+        let digest = version.verifier_parameters_digest();
+        ensure!(
+            self.get(&digest).is_none(),
+            "verifier parameters {digest} are already registered"
+        );
+        self.versions.push((digest, version));
+        Ok(digest)
+    }
+
+    /// Marks a version as revoked, its proofs are rejected from then on.
+    pub fn revoke(&mut self, verifier_parameters: &Digest) -> Result<()> {
+        match self
+            .versions
+            .iter_mut()
+            .find(|(digest, _)| digest == verifier_parameters)
+        {
+            Some((_, version)) => version.revoked = true,
+            None => bail!("unknown verifier parameters: {verifier_parameters}"),
+        }
+        Ok(())
+    }
+
+    /// Returns the version with these verifier parameters, even if it is revoked.
+    pub fn get(&self, verifier_parameters: &Digest) -> Option<&CircuitVersion> {
+        self.versions
+            .iter()
+            .find(|(digest, _)| digest == verifier_parameters)
+            .map(|(_, version)| version)
+    }
+
+    /// Returns the version with these verifier parameters, or an error if it is unknown or
+    /// revoked.
+    pub fn lookup(&self, verifier_parameters: &Digest) -> Result<&CircuitVersion> {
+        let Some(version) = self.get(verifier_parameters) else {
+            bail!("unknown BitVM2 verifier parameters: {verifier_parameters}");
+        };
+        ensure!(
+            !version.revoked,
+            "BitVM2 verifier parameters {verifier_parameters} (circuit {}) are revoked",
+            version.circuit_commit
+        );
+        Ok(version)
+    }
+
+    pub fn versions(&self) -> impl Iterator<Item = (&Digest, &CircuitVersion)> {
+        self.versions
+            .iter()
+            .map(|(digest, version)| (digest, version))
+    }
+}
+
+impl Default for VersionRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn old_version() -> CircuitVersion {
+        CircuitVersion {
+            control_root: Digest::from([1u32; 8]),
+            circuit_commit: "0".repeat(40),
+            ..CircuitVersion::current()
+        }
+    }
+
+    #[test]
+    fn builtin() {
+        let registry = VersionRegistry::builtin();
+        let current = CircuitVersion::current();
+        assert_eq!(
+            current.verifier_parameters_digest(),
+            crate::verify::verifier_parameters_digest()
+        );
+        assert_eq!(
+            registry
+                .lookup(&current.verifier_parameters_digest())
+                .unwrap(),
+            &current
+        );
+        assert!(registry.lookup(&Digest::ZERO).is_err());
+        // Standard risc0 Groth16 receipts are not BitVM2 receipts
+        assert!(
+            registry
+                .lookup(&risc0_zkvm::Groth16ReceiptVerifierParameters::default().digest())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn revoke() {
+        let mut registry = VersionRegistry::builtin();
+        let digest = registry.insert(old_version()).unwrap();
+        assert_eq!(digest, old_version().verifier_parameters_digest());
+        assert_ne!(
+            digest,
+            CircuitVersion::current().verifier_parameters_digest()
+        );
+        assert!(registry.insert(old_version()).is_err());
+        assert_eq!(registry.lookup(&digest).unwrap(), &old_version());
+
+        registry.revoke(&digest).unwrap();
+        assert!(registry.get(&digest).unwrap().revoked);
+        let err = registry.lookup(&digest).unwrap_err();
+        assert!(err.to_string().contains("revoked"));
+        assert!(registry.revoke(&Digest::ZERO).is_err());
+    }
+}
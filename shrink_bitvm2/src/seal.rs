@@ -0,0 +1,349 @@
+//! Compact encoding for BitVM2 Groth16 seals.
+//!
+//! The uncompressed seal is the 256-byte `A || B || C` layout used by [Groth16Seal]. The
+//! compressed form only keeps the x-coordinate of each point (big-endian, `x.c1 || x.c0` for
+//! G2) and stores the y-coordinate choice in the two most significant bits of the first byte,
+//! which are always free because the BN254 base field modulus is below 2^254:
+//!
+//! - `0x80`: y is the lexicographically greatest of the two square roots.
+//! - `0x40`: the point is the point at infinity (all other bits must be zero).
+
+use anyhow::{Context, Result, bail, ensure};
+use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+use ark_ff::{BigInt, BigInteger, PrimeField};
+use risc0_groth16::Seal as Groth16Seal;
+
+pub const UNCOMPRESSED_SEAL_SIZE: usize = 256;
+pub const COMPRESSED_SEAL_SIZE: usize = 128;
+
+const FIELD_SIZE: usize = 32;
+const FLAG_GREATEST: u8 = 0x80;
+const FLAG_INFINITY: u8 = 0x40;
+const FLAG_MASK: u8 = FLAG_GREATEST | FLAG_INFINITY;
+
+/// Encodes a seal in the 128-byte compressed form.
+pub fn compress_seal(seal: &Groth16Seal) -> Result<[u8; COMPRESSED_SEAL_SIZE]> {
+    let (a, b, c) = seal_to_points(seal)?;
+
+    let mut out = [0u8; COMPRESSED_SEAL_SIZE];
+    out[0..32].copy_from_slice(&compress_g1(&a));
+    out[32..96].copy_from_slice(&compress_g2(&b));
+    out[96..128].copy_from_slice(&compress_g1(&c));
+    Ok(out)
+}
+
+/// Decodes a seal from the 128-byte compressed form.
+pub fn decompress_seal(bytes: &[u8]) -> Result<Groth16Seal> {
+    ensure!(
+        bytes.len() == COMPRESSED_SEAL_SIZE,
+        "compressed seal must be {COMPRESSED_SEAL_SIZE} bytes, got {}",
+        bytes.len()
+    );
+    let a = decompress_g1(&bytes[0..32]).context("invalid seal point A")?;
+    let b = decompress_g2(&bytes[32..96]).context("invalid seal point B")?;
+    let c = decompress_g1(&bytes[96..128]).context("invalid seal point C")?;
+    points_to_seal(&a, &b, &c)
+}
+
+/// Decodes a seal from either the compressed or the uncompressed form, based on its length.
+pub fn decode_seal(bytes: &[u8]) -> Result<Groth16Seal> {
+    match bytes.len() {
+        COMPRESSED_SEAL_SIZE => decompress_seal(bytes),
+        UNCOMPRESSED_SEAL_SIZE => {
+            let seal = Groth16Seal::from_vec(bytes)?;
+            // Reject seals whose points are not valid curve points
+            seal_to_points(&seal)?;
+            Ok(seal)
+        }
+        len => bail!(
+            "seal must be {COMPRESSED_SEAL_SIZE} or {UNCOMPRESSED_SEAL_SIZE} bytes, got {len}"
+        ),
+    }
+}
+
+/// A seal in either the compressed or the uncompressed form, as accepted by
+/// [crate::verify::verify_integrity].
+pub trait EncodedSeal {
+    /// The 256-byte uncompressed seal.
+    fn to_uncompressed(&self) -> Result<Vec<u8>>;
+}
+
+impl EncodedSeal for Groth16Seal {
+    fn to_uncompressed(&self) -> Result<Vec<u8>> {
+        Ok(self.to_vec())
+    }
+}
+
+impl EncodedSeal for [u8] {
+    fn to_uncompressed(&self) -> Result<Vec<u8>> {
+        Ok(decode_seal(self)?.to_vec())
+    }
+}
+
+impl EncodedSeal for Vec<u8> {
+    fn to_uncompressed(&self) -> Result<Vec<u8>> {
+        self.as_slice().to_uncompressed()
+    }
+}
+
+impl<const N: usize> EncodedSeal for [u8; N] {
+    fn to_uncompressed(&self) -> Result<Vec<u8>> {
+        self.as_slice().to_uncompressed()
+    }
+}
+
+fn seal_to_points(seal: &Groth16Seal) -> Result<(G1Affine, G2Affine, G1Affine)> {
+    let bytes = seal.to_vec();
+    ensure!(
+        bytes.len() == UNCOMPRESSED_SEAL_SIZE,
+        "seal must be {UNCOMPRESSED_SEAL_SIZE} bytes, got {}",
+        bytes.len()
+    );
+
+    let a = g1_from_uncompressed(&bytes[0..32], &bytes[32..64]).context("invalid seal point A")?;
+    let b =
+        g2_from_uncompressed(&bytes[64..128], &bytes[128..192]).context("invalid seal point B")?;
+    let c =
+        g1_from_uncompressed(&bytes[192..224], &bytes[224..256]).context("invalid seal point C")?;
+    Ok((a, b, c))
+}
+
+fn points_to_seal(a: &G1Affine, b: &G2Affine, c: &G1Affine) -> Result<Groth16Seal> {
+    let mut bytes = Vec::with_capacity(UNCOMPRESSED_SEAL_SIZE);
+    bytes.extend(g1_to_uncompressed(a));
+    bytes.extend(g2_to_uncompressed(b));
+    bytes.extend(g1_to_uncompressed(c));
+    Groth16Seal::from_vec(&bytes)
+}
+
+fn g1_from_uncompressed(x: &[u8], y: &[u8]) -> Result<G1Affine> {
+    if x.iter().chain(y).all(|&byte| byte == 0) {
+        return Ok(G1Affine::identity());
+    }
+    let point = G1Affine::new_unchecked(fq_from_be_bytes(x)?, fq_from_be_bytes(y)?);
+    ensure!(point.is_on_curve(), "point is not on the curve");
+    ensure!(
+        point.is_in_correct_subgroup_assuming_on_curve(),
+        "point is not in the prime order subgroup"
+    );
+    Ok(point)
+}
+
+fn g2_from_uncompressed(x: &[u8], y: &[u8]) -> Result<G2Affine> {
+    if x.iter().chain(y).all(|&byte| byte == 0) {
+        return Ok(G2Affine::identity());
+    }
+    let point = G2Affine::new_unchecked(fq2_from_be_bytes(x)?, fq2_from_be_bytes(y)?);
+    ensure!(point.is_on_curve(), "point is not on the curve");
+    ensure!(
+        point.is_in_correct_subgroup_assuming_on_curve(),
+        "point is not in the prime order subgroup"
+    );
+    Ok(point)
+}
+
+fn g1_to_uncompressed(point: &G1Affine) -> Vec<u8> {
+    if point.infinity {
+        return vec![0u8; 2 * FIELD_SIZE];
+    }
+    [fq_to_be_bytes(&point.x), fq_to_be_bytes(&point.y)].concat()
+}
+
+fn g2_to_uncompressed(point: &G2Affine) -> Vec<u8> {
+    if point.infinity {
+        return vec![0u8; 4 * FIELD_SIZE];
+    }
+    [fq2_to_be_bytes(&point.x), fq2_to_be_bytes(&point.y)].concat()
+}
+
+fn compress_g1(point: &G1Affine) -> [u8; FIELD_SIZE] {
+    let mut out = [0u8; FIELD_SIZE];
+    if point.infinity {
+        out[0] = FLAG_INFINITY;
+        return out;
+    }
+    out.copy_from_slice(&fq_to_be_bytes(&point.x));
+    if point.y > -point.y {
+        out[0] |= FLAG_GREATEST;
+    }
+    out
+}
+
+fn compress_g2(point: &G2Affine) -> [u8; 2 * FIELD_SIZE] {
+    let mut out = [0u8; 2 * FIELD_SIZE];
+    if point.infinity {
+        out[0] = FLAG_INFINITY;
+        return out;
+    }
+    out.copy_from_slice(&fq2_to_be_bytes(&point.x));
+    if point.y > -point.y {
+        out[0] |= FLAG_GREATEST;
+    }
+    out
+}
+
+/// Splits the flag bits off the first byte of a compressed point.
+fn split_flags(bytes: &[u8]) -> (u8, Vec<u8>) {
+    let flags = bytes[0] & FLAG_MASK;
+    let mut x = bytes.to_vec();
+    x[0] &= !FLAG_MASK;
+    (flags, x)
+}
+
+fn decompress_g1(bytes: &[u8]) -> Result<G1Affine> {
+    let (flags, x) = split_flags(bytes);
+    if flags & FLAG_INFINITY != 0 {
+        ensure!(
+            flags == FLAG_INFINITY && x.iter().all(|&byte| byte == 0),
+            "malformed point at infinity"
+        );
+        return Ok(G1Affine::identity());
+    }
+    let x = fq_from_be_bytes(&x)?;
+    let point = G1Affine::get_point_from_x_unchecked(x, flags & FLAG_GREATEST != 0)
+        .context("x-coordinate is not on the curve")?;
+    ensure!(
+        point.is_in_correct_subgroup_assuming_on_curve(),
+        "point is not in the prime order subgroup"
+    );
+    Ok(point)
+}
+
+fn decompress_g2(bytes: &[u8]) -> Result<G2Affine> {
+    let (flags, x) = split_flags(bytes);
+    if flags & FLAG_INFINITY != 0 {
+        ensure!(
+            flags == FLAG_INFINITY && x.iter().all(|&byte| byte == 0),
+            "malformed point at infinity"
+        );
+        return Ok(G2Affine::identity());
+    }
+    let x = fq2_from_be_bytes(&x)?;
+    let point = G2Affine::get_point_from_x_unchecked(x, flags & FLAG_GREATEST != 0)
+        .context("x-coordinate is not on the curve")?;
+    ensure!(
+        point.is_in_correct_subgroup_assuming_on_curve(),
+        "point is not in the prime order subgroup"
+    );
+    Ok(point)
+}
+
+fn fq_from_be_bytes(bytes: &[u8]) -> Result<Fq> {
+    ensure!(bytes.len() == FIELD_SIZE, "field element must be 32 bytes");
+    let mut limbs = [0u64; 4];
+    for (limb, chunk) in limbs.iter_mut().zip(bytes.rchunks_exact(8)) {
+        *limb = u64::from_be_bytes(chunk.try_into()?);
+    }
+    Fq::from_bigint(BigInt::new(limbs)).context("field element is not in canonical form")
+}
+
+fn fq_to_be_bytes(value: &Fq) -> Vec<u8> {
+    value.into_bigint().to_bytes_be()
+}
+
+/// Decodes an Fq2 element stored as `c1 || c0`, matching the seal layout.
+fn fq2_from_be_bytes(bytes: &[u8]) -> Result<Fq2> {
+    ensure!(
+        bytes.len() == 2 * FIELD_SIZE,
+        "Fq2 element must be 64 bytes"
+    );
+    let c1 = fq_from_be_bytes(&bytes[..FIELD_SIZE])?;
+    let c0 = fq_from_be_bytes(&bytes[FIELD_SIZE..])?;
+    Ok(Fq2::new(c0, c1))
+}
+
+fn fq2_to_be_bytes(value: &Fq2) -> Vec<u8> {
+    [fq_to_be_bytes(&value.c1), fq_to_be_bytes(&value.c0)].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::{Bitvm2VerifyingKey, verify_integrity_with_key};
+    use ark_bn254::{
+        Bn254, Fr,
+        g1::{G1_GENERATOR_X, G1_GENERATOR_Y},
+        g2::{G2_GENERATOR_X, G2_GENERATOR_Y},
+    };
+    use ark_ff::Field;
+    use ark_groth16::VerifyingKey;
+    use proptest::prelude::*;
+
+    fn scalar() -> impl Strategy<Value = Fr> {
+        any::<[u8; 32]>().prop_map(|bytes| Fr::from_be_bytes_mod_order(&bytes))
+    }
+
+    /// A proof of `input` which verifies against the returned key. The key is built from known
+    /// scalars, so C can be solved from the verification equation
+    /// `e(A, B) = e(alpha, beta) e(IC0 + input IC1, gamma) e(C, delta)` without a circuit.
+    fn valid_proof(scalars: [Fr; 8], input: Fr) -> (VerifyingKey<Bn254>, Groth16Seal) {
+        let [a, b, alpha, beta, gamma, delta, ic0, ic1] = scalars;
+        let g1 = G1Affine::new(G1_GENERATOR_X, G1_GENERATOR_Y);
+        let g2 = G2Affine::new(G2_GENERATOR_X, G2_GENERATOR_Y);
+        let c = (a * b - alpha * beta - (ic0 + input * ic1) * gamma) * delta.inverse().unwrap();
+
+        let vk = VerifyingKey {
+            alpha_g1: (g1 * alpha).into(),
+            beta_g2: (g2 * beta).into(),
+            gamma_g2: (g2 * gamma).into(),
+            delta_g2: (g2 * delta).into(),
+            gamma_abc_g1: vec![(g1 * ic0).into(), (g1 * ic1).into()],
+        };
+        let seal = points_to_seal(&(g1 * a).into(), &(g2 * b).into(), &(g1 * c).into()).unwrap();
+        (vk, seal)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn compressed_proof_verifies(
+            scalars in proptest::array::uniform8(scalar()),
+            claim_digest in any::<[u8; 32]>(),
+        ) {
+            prop_assume!(scalars[5] != Fr::from(0u8));
+            let input = Fr::from_be_bytes_mod_order(&claim_digest);
+            let (vk, seal) = valid_proof(scalars, input);
+            let key = Bitvm2VerifyingKey::InsecureDev(vk);
+
+            let compressed = compress_seal(&seal).unwrap();
+            prop_assert_eq!(&decompress_seal(&compressed).unwrap(), &seal);
+            prop_assert_eq!(&decode_seal(&compressed).unwrap(), &seal);
+            prop_assert_eq!(&decode_seal(&seal.to_vec()).unwrap(), &seal);
+
+            verify_integrity_with_key(&seal, &claim_digest, &key).unwrap();
+            verify_integrity_with_key(&compressed, &claim_digest, &key).unwrap();
+            verify_integrity_with_key(seal.to_vec().as_slice(), &claim_digest, &key).unwrap();
+
+            let mut other_digest = claim_digest;
+            other_digest[31] ^= 1;
+            prop_assert!(verify_integrity_with_key(&compressed, &other_digest, &key).is_err());
+        }
+    }
+
+    #[test]
+    fn infinity_round_trip() {
+        let seal = Groth16Seal::from_vec(&[0u8; UNCOMPRESSED_SEAL_SIZE]).unwrap();
+        let compressed = compress_seal(&seal).unwrap();
+        assert_eq!(compressed[0], FLAG_INFINITY);
+        assert_eq!(compressed[32], FLAG_INFINITY);
+        assert_eq!(compressed[96], FLAG_INFINITY);
+        assert_eq!(decompress_seal(&compressed).unwrap(), seal);
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let scalars = [1u8, 2, 3, 4, 5, 6, 7, 8].map(Fr::from);
+        let (_, seal) = valid_proof(scalars, Fr::from(9u8));
+        let mut compressed = compress_seal(&seal).unwrap();
+        assert!(decompress_seal(&compressed[..127]).is_err());
+        assert!(decode_seal(&compressed[..100]).is_err());
+
+        compressed[0] |= FLAG_INFINITY;
+        assert!(decompress_seal(&compressed).is_err());
+
+        let mut uncompressed = seal.to_vec();
+        uncompressed[63] ^= 1;
+        assert!(decode_seal(&uncompressed).is_err());
+    }
+}
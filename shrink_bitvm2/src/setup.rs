@@ -0,0 +1,332 @@
+//! Groth16 setup directory (`RISC0_BVM2_SETUP_DIR`) and the manifest `cargo xtask setup-groth16`
+//! writes into it.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail, ensure};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use crate::{bundle::CIRCUIT_COMMIT, verify::Bitvm2VerifyingKey};
+
+pub const MANIFEST_FILE: &str = "setup_manifest.json";
+pub const MANIFEST_VERSION: u32 = 1;
+
+pub const ZKEY_FILE: &str = "verify_for_guest_final.zkey";
+pub const GRAPH_FILE: &str = "verify_for_guest_graph.bin";
+/// snarkjs verifying key written by `cargo xtask dev-setup`.
+pub const DEV_VK_FILE: &str = "verification_key.json";
+/// Set to `1` to prove with a setup made by `cargo xtask dev-setup`.
+pub const ALLOW_INSECURE_DEV_ENV: &str = "SHRINK_BVM2_ALLOW_INSECURE_DEV_SETUP";
+
+/// A file of the setup directory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetupArtifact {
+    /// Path relative to the setup directory.
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetupManifest {
+    pub version: u32,
+    /// Commit of `risc0-to-bitvm2` the circuit was built from.
+    pub circuit_commit: String,
+    /// Source repositories and the commit each was checked out at.
+    pub sources: BTreeMap<String, String>,
+    /// Versions of the tools used to build the artifacts.
+    pub tools: BTreeMap<String, String>,
+    pub artifacts: Vec<SetupArtifact>,
+    /// Whether the `risc0_groth16_sys::setup` preprocessing completed.
+    pub preprocessed: bool,
+    /// INSECURE: whether the zkey comes from the single-party `cargo xtask dev-setup` instead of
+    /// the production trusted setup.
+    #[serde(default)]
+    pub insecure_dev: bool,
+}
+
+impl SetupManifest {
+    pub fn artifact(&self, path: &str) -> Option<&SetupArtifact> {
+        self.artifacts.iter().find(|artifact| artifact.path == path)
+    }
+
+    /// SHA-256 of the circuit commit and the artifact hashes, which identifies the setup.
+    pub fn fingerprint(&self) -> String {
+        let mut artifacts: Vec<_> = self.artifacts.iter().collect();
+        artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut hasher = Sha256::new();
+        hasher.update(self.circuit_commit.as_bytes());
+        for artifact in artifacts {
+            hasher.update(format!("\n{} {}", artifact.sha256, artifact.path));
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SetupDir {
+    dir: PathBuf,
+}
+
+impl SetupDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let dir = std::env::var_os("RISC0_BVM2_SETUP_DIR")
+            .context("must provide RISC0_BVM2_SETUP_DIR")?;
+        Ok(Self::new(dir))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn zkey_path(&self) -> PathBuf {
+        self.dir.join(ZKEY_FILE)
+    }
+
+    pub fn graph_path(&self) -> PathBuf {
+        self.dir.join(GRAPH_FILE)
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.dir.join(MANIFEST_FILE)
+    }
+
+    pub fn read_manifest(&self) -> Result<SetupManifest> {
+        let path = self.manifest_path();
+        let json = std::fs::read_to_string(&path).with_context(|| {
+            format!(
+                "failed to read {}, run `cargo xtask setup-groth16`",
+                path.display()
+            )
+        })?;
+        serde_json::from_str(&json).with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    pub fn write_manifest(&self, manifest: &SetupManifest) -> Result<()> {
+        let path = self.manifest_path();
+        let tmp_path = self.dir.join(format!("{MANIFEST_FILE}.tmp"));
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(manifest)?)
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &path)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Hashes the file at `path`, relative to the setup directory, for the manifest.
+    pub fn artifact(&self, path: &str) -> Result<SetupArtifact> {
+        let full_path = self.dir.join(path);
+        Ok(SetupArtifact {
+            path: path.to_string(),
+            sha256: sha256_file(&full_path)?,
+            size: std::fs::metadata(&full_path)
+                .with_context(|| format!("failed to read {}", full_path.display()))?
+                .len(),
+        })
+    }
+
+    /// Checks that the setup is complete and matches this crate's circuit. Only file sizes are
+    /// checked, see [SetupDir::verify_hashes] for a full check.
+    pub fn validate(&self) -> Result<SetupManifest> {
+        let manifest = self.read_manifest()?;
+        ensure!(
+            manifest.version == MANIFEST_VERSION,
+            "unsupported setup manifest version {}, run `cargo xtask setup-groth16`",
+            manifest.version
+        );
+        ensure!(
+            manifest.circuit_commit == CIRCUIT_COMMIT,
+            "setup was built from circuit commit {}, expected {CIRCUIT_COMMIT}",
+            manifest.circuit_commit
+        );
+        let dev_files = manifest.insecure_dev.then_some(DEV_VK_FILE);
+        for required in [ZKEY_FILE, GRAPH_FILE].into_iter().chain(dev_files) {
+            if manifest.artifact(required).is_none() {
+                bail!("setup manifest does not list {required}");
+            }
+        }
+        #[cfg(feature = "cuda")]
+        ensure!(
+            manifest.preprocessed,
+            "setup preprocessing did not complete, run `cargo xtask setup-groth16`"
+        );
+
+        for artifact in &manifest.artifacts {
+            let path = self.dir.join(&artifact.path);
+            let size = std::fs::metadata(&path)
+                .with_context(|| format!("setup artifact {} is missing", path.display()))?
+                .len();
+            ensure!(
+                size == artifact.size,
+                "setup artifact {} is {size} bytes, expected {}",
+                path.display(),
+                artifact.size
+            );
+        }
+        Ok(manifest)
+    }
+
+    /// Verifying key matching the zkey of this setup. A setup made by `cargo xtask dev-setup`
+    /// is rejected unless [ALLOW_INSECURE_DEV_ENV] is set to `1`, see
+    /// [SetupDir::insecure_dev_verifying_key].
+    pub fn verifying_key(&self) -> Result<Bitvm2VerifyingKey> {
+        if !self.read_manifest()?.insecure_dev {
+            return Ok(Bitvm2VerifyingKey::Production);
+        }
+        ensure!(
+            std::env::var(ALLOW_INSECURE_DEV_ENV).is_ok_and(|value| value == "1"),
+            "{} was made by `cargo xtask dev-setup` and is INSECURE, set \
+             {ALLOW_INSECURE_DEV_ENV}=1 to use it for testing",
+            self.dir.display()
+        );
+        self.insecure_dev_verifying_key()
+    }
+
+    /// INSECURE: verifying key of a setup made by `cargo xtask dev-setup`, for tests which use
+    /// it on purpose.
+    pub fn insecure_dev_verifying_key(&self) -> Result<Bitvm2VerifyingKey> {
+        ensure!(
+            self.read_manifest()?.insecure_dev,
+            "{} is not a `cargo xtask dev-setup` setup",
+            self.dir.display()
+        );
+        tracing::warn!(
+            "{} was made by `cargo xtask dev-setup` and is INSECURE",
+            self.dir.display()
+        );
+        let path = self.dir.join(DEV_VK_FILE);
+        let json = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Bitvm2VerifyingKey::insecure_dev_from_snarkjs_json(&json)
+    }
+
+    /// Same as [SetupDir::validate], and also checks the SHA-256 of every artifact.
+    pub fn verify_hashes(&self) -> Result<SetupManifest> {
+        let manifest = self.validate()?;
+        for artifact in &manifest.artifacts {
+            let path = self.dir.join(&artifact.path);
+            let sha256 = sha256_file(&path)?;
+            ensure!(
+                sha256 == artifact.sha256,
+                "setup artifact {} has SHA-256 {sha256}, expected {}",
+                path.display(),
+                artifact.sha256
+            );
+        }
+        Ok(manifest)
+    }
+}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_dir() -> (tempfile::TempDir, SetupDir, SetupManifest) {
+        let dir = tempfile::tempdir().unwrap();
+        let setup_dir = SetupDir::new(dir.path());
+        std::fs::write(setup_dir.zkey_path(), b"zkey").unwrap();
+        std::fs::write(setup_dir.graph_path(), b"graph").unwrap();
+        let manifest = SetupManifest {
+            version: MANIFEST_VERSION,
+            circuit_commit: CIRCUIT_COMMIT.to_string(),
+            sources: BTreeMap::new(),
+            tools: BTreeMap::new(),
+            artifacts: vec![
+                setup_dir.artifact(ZKEY_FILE).unwrap(),
+                setup_dir.artifact(GRAPH_FILE).unwrap(),
+            ],
+            preprocessed: true,
+            insecure_dev: false,
+        };
+        setup_dir.write_manifest(&manifest).unwrap();
+        (dir, setup_dir, manifest)
+    }
+
+    #[test]
+    fn validate() {
+        let (_dir, setup_dir, manifest) = setup_dir();
+        assert_eq!(setup_dir.verify_hashes().unwrap(), manifest);
+
+        // Same size, different content
+        std::fs::write(setup_dir.graph_path(), b"GRAPH").unwrap();
+        setup_dir.validate().unwrap();
+        assert!(setup_dir.verify_hashes().is_err());
+
+        std::fs::remove_file(setup_dir.zkey_path()).unwrap();
+        assert!(setup_dir.validate().is_err());
+    }
+
+    #[test]
+    fn fingerprint() {
+        let (_dir, setup_dir, mut manifest) = setup_dir();
+        let fingerprint = manifest.fingerprint();
+        manifest.artifacts.reverse();
+        assert_eq!(manifest.fingerprint(), fingerprint);
+
+        std::fs::write(setup_dir.graph_path(), b"GRAPH").unwrap();
+        manifest.artifacts = vec![
+            setup_dir.artifact(ZKEY_FILE).unwrap(),
+            setup_dir.artifact(GRAPH_FILE).unwrap(),
+        ];
+        assert_ne!(manifest.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn rejects_other_circuit() {
+        let (_dir, setup_dir, mut manifest) = setup_dir();
+        manifest.circuit_commit = "0".repeat(40);
+        setup_dir.write_manifest(&manifest).unwrap();
+        assert!(setup_dir.validate().is_err());
+
+        std::fs::remove_file(setup_dir.manifest_path()).unwrap();
+        assert!(setup_dir.validate().is_err());
+    }
+
+    #[test]
+    fn insecure_dev_setup_requires_key() {
+        let (_dir, setup_dir, mut manifest) = setup_dir();
+        assert_eq!(
+            setup_dir.verifying_key().unwrap(),
+            Bitvm2VerifyingKey::Production
+        );
+
+        assert!(setup_dir.insecure_dev_verifying_key().is_err());
+
+        manifest.insecure_dev = true;
+        setup_dir.write_manifest(&manifest).unwrap();
+        assert!(setup_dir.validate().is_err());
+        assert!(setup_dir.verifying_key().is_err());
+
+        // The dev key is only used when asked for explicitly
+        let json = include_str!("../verification_key.json");
+        std::fs::write(setup_dir.path().join(DEV_VK_FILE), json).unwrap();
+        manifest
+            .artifacts
+            .push(setup_dir.artifact(DEV_VK_FILE).unwrap());
+        setup_dir.write_manifest(&manifest).unwrap();
+        setup_dir.validate().unwrap();
+        let err = setup_dir.verifying_key().unwrap_err();
+        assert!(err.to_string().contains(ALLOW_INSECURE_DEV_ENV), "{err:?}");
+        assert!(
+            setup_dir
+                .insecure_dev_verifying_key()
+                .unwrap()
+                .is_insecure()
+        );
+    }
+}
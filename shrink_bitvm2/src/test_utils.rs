@@ -0,0 +1,28 @@
+//! Proofs of the echo guest shared by the tests.
+
+use guest::ECHO_ELF;
+use risc0_zkvm::{ExecutorEnv, ProverOpts, Receipt, ReceiptClaim, SuccinctReceipt, default_prover};
+
+/// Proves the echo guest with `input` as its journal, returning the succinct receipt.
+pub(crate) fn prove_echo(input: &[u8]) -> SuccinctReceipt<ReceiptClaim> {
+    let env = ExecutorEnv::builder().write_slice(input).build().unwrap();
+    let receipt = default_prover()
+        .prove_with_opts(env, ECHO_ELF, &ProverOpts::succinct())
+        .unwrap()
+        .receipt;
+    receipt.inner.succinct().unwrap().clone()
+}
+
+/// Same as [prove_echo], also returning the receipt shrunk by `identity_p254`.
+pub(crate) fn prove_echo_p254(
+    input: &[u8],
+) -> (SuccinctReceipt<ReceiptClaim>, SuccinctReceipt<ReceiptClaim>) {
+    let succinct_receipt = prove_echo(input);
+    let p254_receipt = risc0_zkvm::recursion::identity_p254(&succinct_receipt).unwrap();
+    (succinct_receipt, p254_receipt)
+}
+
+/// Same as [prove_echo], shrunk to a BitVM2 receipt.
+pub(crate) fn prove_echo_bitvm2(input: &[u8]) -> Receipt {
+    crate::succinct_to_bitvm2(&prove_echo(input), input).unwrap()
+}
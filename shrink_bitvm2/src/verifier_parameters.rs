@@ -0,0 +1,61 @@
+use risc0_binfmt::{Digestible, tagged_struct};
+use risc0_zkvm::{Digest, sha::Sha256};
+
+use crate::verify::Bitvm2VerifyingKey;
+
+/// Version of the [crate::ShrinkBitvm2ReceiptClaim] digest computation.
+pub const CLAIM_DIGEST_VERSION: u32 = 1;
+
+const TAG: &str = "shrink_bitvm2.Bitvm2ReceiptVerifierParameters";
+
+/// Everything a BitVM2 Groth16 proof depends on. Its digest is the `verifier_parameters` of the
+/// receipts built by [crate::finalize], and identifies a [crate::CircuitVersion] in bundles and
+/// in the [crate::VersionRegistry].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bitvm2ReceiptVerifierParameters {
+    pub verifying_key: Bitvm2VerifyingKey,
+    /// Control root of the succinct receipts the circuit verifies.
+    pub control_root: Digest,
+    /// Control ID of the `identity_p254` program.
+    pub bn254_control_id: Digest,
+    pub claim_digest_version: u32,
+}
+
+impl Digestible for Bitvm2ReceiptVerifierParameters {
+    fn digest<S: Sha256>(&self) -> Digest {
+        tagged_struct::<S>(
+            TAG,
+            &[
+                self.verifying_key.r0_key().digest::<S>(),
+                self.control_root,
+                self.bn254_control_id,
+            ],
+            &[self.claim_digest_version],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::CircuitVersion;
+    use risc0_zkvm::{Groth16ReceiptVerifierParameters, sha::Impl};
+
+    #[test]
+    fn domain_separated() {
+        let params = CircuitVersion::current().verifier_parameters();
+        let groth16_params = Groth16ReceiptVerifierParameters {
+            control_root: params.control_root,
+            bn254_control_id: params.bn254_control_id,
+            verifying_key: params.verifying_key.r0_key(),
+            ..Default::default()
+        };
+        assert_ne!(params.digest::<Impl>(), groth16_params.digest::<Impl>());
+
+        let other = Bitvm2ReceiptVerifierParameters {
+            claim_digest_version: CLAIM_DIGEST_VERSION + 1,
+            ..params.clone()
+        };
+        assert_ne!(params.digest::<Impl>(), other.digest::<Impl>());
+    }
+}
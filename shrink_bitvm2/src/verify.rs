@@ -1,23 +1,160 @@
-use anyhow::{Result, ensure};
+use anyhow::{Result, anyhow, bail, ensure};
 use ark_serialize::CanonicalSerialize;
 use risc0_groth16::Seal as Groth16Seal;
-use risc0_zkvm::{Digest, sha::Digestible};
+use risc0_zkvm::{Digest, Receipt, sha::Digestible};
+use serde::Deserialize;
 
-use crate::ShrinkBitvm2ReceiptClaim;
+use crate::{ReceiptKind, ShrinkBitvm2ReceiptClaim, registry::CircuitVersion, seal::EncodedSeal};
 
-pub fn verify_integrity(seal: &Groth16Seal, output_bytes: &[u8]) -> Result<()> {
+#[rustfmt::skip]
+mod verifying_key;
+
+/// Groth16 verifying key of the `verify_for_guest` circuit.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Bitvm2VerifyingKey {
+    /// Key of the production trusted setup.
+    Production,
+    /// INSECURE: key of a single-party setup from `cargo xtask dev-setup`, whose toxic waste is
+    /// known. Proofs verified with it prove nothing, it must only be used for testing.
+    InsecureDev(ark_groth16::VerifyingKey<ark_bn254::Bn254>),
+}
+
+impl Bitvm2VerifyingKey {
+    /// Reads an insecure development key from a snarkjs `verification_key.json`.
+    pub fn insecure_dev_from_snarkjs_json(json: &str) -> Result<Self> {
+        Ok(Self::InsecureDev(ark_key_from_snarkjs_json(json)?))
+    }
+
+    pub fn is_insecure(&self) -> bool {
+        matches!(self, Self::InsecureDev(_))
+    }
+
+    pub fn ark_key(&self) -> ark_groth16::VerifyingKey<ark_bn254::Bn254> {
+        match self {
+            Self::Production => get_ark_verifying_key(),
+            Self::InsecureDev(vk) => vk.clone(),
+        }
+    }
+
+    pub fn r0_key(&self) -> risc0_groth16::VerifyingKey {
+        r0_key_from_ark(&self.ark_key())
+    }
+
+    /// Verifier parameters digest of the current circuit version proven with this key.
+    pub fn verifier_parameters_digest(&self) -> Digest {
+        CircuitVersion {
+            verifying_key: self.clone(),
+            ..CircuitVersion::current()
+        }
+        .verifier_parameters_digest()
+    }
+}
+
+/// Reads a snarkjs `verification_key.json` of the `verify_for_guest` circuit.
+pub(crate) fn ark_key_from_snarkjs_json(
+    json: &str,
+) -> Result<ark_groth16::VerifyingKey<ark_bn254::Bn254>> {
+    let vk: SnarkjsVerifyingKey = serde_json::from_str(json)?;
+    ensure!(
+        vk.protocol == "groth16" && vk.curve == "bn128",
+        "expected a groth16 bn128 key, got {} {}",
+        vk.protocol,
+        vk.curve
+    );
+    ensure!(
+        vk.n_public == 1 && vk.ic.len() == 2,
+        "expected a key with 1 public input, got {}",
+        vk.n_public
+    );
+    Ok(ark_groth16::VerifyingKey {
+        alpha_g1: snarkjs_g1(&vk.vk_alpha_1)?,
+        beta_g2: snarkjs_g2(&vk.vk_beta_2)?,
+        gamma_g2: snarkjs_g2(&vk.vk_gamma_2)?,
+        delta_g2: snarkjs_g2(&vk.vk_delta_2)?,
+        gamma_abc_g1: vk.ic.iter().map(|p| snarkjs_g1(p)).collect::<Result<_>>()?,
+    })
+}
+
+#[derive(Deserialize)]
+struct SnarkjsVerifyingKey {
+    protocol: String,
+    curve: String,
+    #[serde(rename = "nPublic")]
+    n_public: usize,
+    vk_alpha_1: Vec<String>,
+    vk_beta_2: Vec<Vec<String>>,
+    vk_gamma_2: Vec<Vec<String>>,
+    vk_delta_2: Vec<Vec<String>>,
+    #[serde(rename = "IC")]
+    ic: Vec<Vec<String>>,
+}
+
+fn snarkjs_fq(value: &str) -> Result<ark_bn254::Fq> {
+    use std::str::FromStr;
+    ark_bn254::Fq::from_str(value).map_err(|_| anyhow!("invalid field element {value}"))
+}
+
+/// Parses a snarkjs `[x, y, z]` projective G1 point, with z = 1.
+fn snarkjs_g1(point: &[String]) -> Result<ark_bn254::G1Affine> {
+    let [x, y, z] = point else {
+        bail!("G1 point must have 3 coordinates");
+    };
+    ensure!(z == "1", "G1 point must be affine");
+    let point = ark_bn254::G1Affine::new_unchecked(snarkjs_fq(x)?, snarkjs_fq(y)?);
+    ensure!(
+        point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve(),
+        "invalid G1 point"
+    );
+    Ok(point)
+}
+
+/// Parses a snarkjs `[[x.c0, x.c1], [y.c0, y.c1], [1, 0]]` projective G2 point.
+fn snarkjs_g2(point: &[Vec<String>]) -> Result<ark_bn254::G2Affine> {
+    let fq2 = |c: &[String]| -> Result<ark_bn254::Fq2> {
+        let [c0, c1] = c else {
+            bail!("Fq2 element must have 2 coefficients");
+        };
+        Ok(ark_bn254::Fq2::new(snarkjs_fq(c0)?, snarkjs_fq(c1)?))
+    };
+    let [x, y, z] = point else {
+        bail!("G2 point must have 3 coordinates");
+    };
+    ensure!(z == &["1", "0"], "G2 point must be affine");
+    let point = ark_bn254::G2Affine::new_unchecked(fq2(x)?, fq2(y)?);
+    ensure!(
+        point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve(),
+        "invalid G2 point"
+    );
+    Ok(point)
+}
+
+/// Verifies a seal in either the [Groth16Seal] form or the compressed or uncompressed bytes of
+/// [crate::seal].
+pub fn verify_integrity(seal: &(impl EncodedSeal + ?Sized), output_bytes: &[u8]) -> Result<()> {
+    verify_integrity_with_key(seal, output_bytes, &Bitvm2VerifyingKey::Production)
+}
+
+/// Same as [verify_integrity], with an explicit verifying key.
+pub fn verify_integrity_with_key(
+    seal: &(impl EncodedSeal + ?Sized),
+    output_bytes: &[u8],
+    key: &Bitvm2VerifyingKey,
+) -> Result<()> {
     use ark_ff::PrimeField;
 
-    let ark_proof = from_seal(&seal.to_vec());
+    if key.is_insecure() {
+        tracing::warn!("verifying with an INSECURE development key");
+    }
+    let ark_proof = from_seal(&seal.to_uncompressed()?);
     let public_input_scalar = ark_bn254::Fr::from_be_bytes_mod_order(output_bytes);
-    let ark_vk = get_ark_verifying_key();
+    let ark_vk = key.ark_key();
     let ark_pvk = ark_groth16::prepare_verifying_key(&ark_vk);
     let res = ark_groth16::Groth16::<ark_bn254::Bn254>::verify_proof(
         &ark_pvk,
         &ark_proof,
         &[public_input_scalar],
     )
-    .unwrap();
+    .map_err(|err| anyhow!("proof verification failed: {err}"))?;
     ensure!(res, "proof verification failed");
     Ok(())
 }
@@ -27,131 +164,80 @@ pub fn verify_proof(
     image_id: impl Into<Digest>,
     journal: Vec<u8>,
 ) -> Result<()> {
-    let bvm2_claim_digest: [u8; 32] = ShrinkBitvm2ReceiptClaim::ok(image_id, journal)
-        .digest()
-        .into();
-
-    verify_integrity(seal, &bvm2_claim_digest)
+    verify_proof_with_version(seal, image_id, journal, &CircuitVersion::current())
 }
 
-pub fn get_ark_verifying_key() -> ark_groth16::VerifyingKey<ark_bn254::Bn254> {
-    use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
-    use std::str::FromStr;
-
-    let alpha_g1 = G1Affine::new(
-        Fq::from_str(
-            "20491192805390485299153009773594534940189261866228447918068658471970481763042",
-        )
-        .unwrap(),
-        Fq::from_str(
-            "9383485363053290200918347156157836566562967994039712273449902621266178545958",
-        )
-        .unwrap(),
-    );
-
-    let beta_g2 = G2Affine::new(
-        Fq2::new(
-            Fq::from_str(
-                "6375614351688725206403948262868962793625744043794305715222011528459656738731",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "4252822878758300859123897981450591353533073413197771768651442665752259397132",
-            )
-            .unwrap(),
-        ),
-        Fq2::new(
-            Fq::from_str(
-                "10505242626370262277552901082094356697409835680220590971873171140371331206856",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "21847035105528745403288232691147584728191162732299865338377159692350059136679",
-            )
-            .unwrap(),
-        ),
+/// Same as [verify_proof], for a proof produced with the circuit `version`.
+pub fn verify_proof_with_version(
+    seal: &Groth16Seal,
+    image_id: impl Into<Digest>,
+    journal: Vec<u8>,
+    version: &CircuitVersion,
+) -> Result<()> {
+    ensure!(
+        !version.revoked,
+        "circuit version {} is revoked",
+        version.circuit_commit
     );
+    let bvm2_claim_digest: [u8; 32] =
+        ShrinkBitvm2ReceiptClaim::for_version(image_id, journal, version)
+            .digest()
+            .into();
 
-    let gamma_g2 = G2Affine::new(
-        Fq2::new(
-            Fq::from_str(
-                "10857046999023057135944570762232829481370756359578518086990519993285655852781",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "11559732032986387107991004021392285783925812861821192530917403151452391805634",
-            )
-            .unwrap(),
-        ),
-        Fq2::new(
-            Fq::from_str(
-                "8495653923123431417604973247489272438418190587263600148770280649306958101930",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "4082367875863433681332203403145435568316851327593401208105741076214120093531",
-            )
-            .unwrap(),
-        ),
-    );
+    verify_integrity_with_key(seal, &bvm2_claim_digest, &version.verifying_key)
+}
 
-    let delta_g2 = G2Affine::new(
-        Fq2::new(
-            Fq::from_str(
-                "19928663713463533589216209779412278386769407450988172849262535478593422929698",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "19916519943909223643323234301580053157586699704876134064841182937085943926141",
-            )
-            .unwrap(),
-        ),
-        Fq2::new(
-            Fq::from_str(
-                "4584600978911428195337731119171761277167808711062125916470525050324985708782",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "903010326261527050999816348900764705196723158942686053018929539519969664840",
-            )
-            .unwrap(),
-        ),
+/// Verifies a receipt produced by [crate::finalize] for `image_id`. BitVM2 receipts commit to
+/// [crate::Bitvm2ReceiptVerifierParameters] and their seal to a [ShrinkBitvm2ReceiptClaim], so
+/// they don't verify with [Receipt::verify].
+pub fn verify_receipt(receipt: &Receipt, image_id: impl Into<Digest>) -> Result<()> {
+    let ReceiptKind::Bitvm2(view) = ReceiptKind::classify(receipt)? else {
+        bail!("receipt is not a BitVM2 receipt");
+    };
+    let image_id = image_id.into();
+    ensure!(
+        view.image_id == image_id,
+        "receipt image ID {} does not match {image_id}",
+        view.image_id
     );
+    view.verify()
+}
 
-    let gamma_abc_g1 = vec![
-        G1Affine::new(
-            Fq::from_str(
-                "6698887085900109660417671413804888867145870700073340970189635830129386206569",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "10431087902009508261375793061696708147989126018612269070732549055898651692604",
-            )
-            .unwrap(),
-        ),
-        G1Affine::new(
-            Fq::from_str(
-                "20225609417084538563062516991929114218412992453664808591983416996515711931386",
-            )
-            .unwrap(),
-            Fq::from_str(
-                "3236310410959095762960658876334609343091075204896196791007975095263664214628",
-            )
-            .unwrap(),
-        ),
-    ];
-
+/// Production verifying key, from the generated `verifying_key` module.
+pub fn get_ark_verifying_key() -> ark_groth16::VerifyingKey<ark_bn254::Bn254> {
     ark_groth16::VerifyingKey {
-        alpha_g1,
-        beta_g2,
-        gamma_g2,
-        delta_g2,
-        gamma_abc_g1,
+        alpha_g1: g1_from_bytes(&verifying_key::ALPHA_G1),
+        beta_g2: g2_from_bytes(&verifying_key::BETA_G2),
+        gamma_g2: g2_from_bytes(&verifying_key::GAMMA_G2),
+        delta_g2: g2_from_bytes(&verifying_key::DELTA_G2),
+        gamma_abc_g1: verifying_key::IC.iter().map(g1_from_bytes).collect(),
     }
 }
 
+fn g1_from_bytes(point: &[[u8; 32]; 2]) -> ark_bn254::G1Affine {
+    use ark_ff::PrimeField;
+    let [x, y] = point.map(|c| ark_bn254::Fq::from_be_bytes_mod_order(&c));
+    ark_bn254::G1Affine::new(x, y)
+}
+
+fn g2_from_bytes(point: &[[[u8; 32]; 2]; 2]) -> ark_bn254::G2Affine {
+    use ark_ff::PrimeField;
+    let [x, y] = point.map(|[c0, c1]| {
+        ark_bn254::Fq2::new(
+            ark_bn254::Fq::from_be_bytes_mod_order(&c0),
+            ark_bn254::Fq::from_be_bytes_mod_order(&c1),
+        )
+    });
+    ark_bn254::G2Affine::new(x, y)
+}
+
 pub fn get_r0_verifying_key() -> risc0_groth16::VerifyingKey {
-    let ark_key = get_ark_verifying_key();
+    r0_key_from_ark(&get_ark_verifying_key())
+}
+
+fn r0_key_from_ark(
+    ark_key: &ark_groth16::VerifyingKey<ark_bn254::Bn254>,
+) -> risc0_groth16::VerifyingKey {
     let mut b = vec![];
     ark_key.serialize_uncompressed(&mut b).unwrap();
     let j = serde_json::to_string(&b).expect("Failed to serialize verification key to JSON");
@@ -161,12 +247,7 @@ pub fn get_r0_verifying_key() -> risc0_groth16::VerifyingKey {
 }
 
 pub fn verifier_parameters_digest() -> Digest {
-    let vk: risc0_groth16::VerifyingKey = get_r0_verifying_key();
-    let groth16_digest = risc0_zkvm::Groth16ReceiptVerifierParameters {
-        verifying_key: vk,
-        ..Default::default()
-    };
-    groth16_digest.digest()
+    CircuitVersion::current().verifier_parameters_digest()
 }
 
 fn from_seal(seal_bytes: &[u8]) -> ark_groth16::Proof<ark_bn254::Bn254> {
@@ -218,4 +299,56 @@ mod test {
         let digest = groth16_digest.digest();
         println!("BITVM2 Verifier digest: {}", digest);
     }
+
+    fn snarkjs_json(vk: &ark_groth16::VerifyingKey<ark_bn254::Bn254>) -> String {
+        let g1 =
+            |p: &ark_bn254::G1Affine| serde_json::json!([p.x.to_string(), p.y.to_string(), "1"]);
+        let g2 = |p: &ark_bn254::G2Affine| {
+            serde_json::json!([
+                [p.x.c0.to_string(), p.x.c1.to_string()],
+                [p.y.c0.to_string(), p.y.c1.to_string()],
+                ["1", "0"]
+            ])
+        };
+        serde_json::json!({
+            "protocol": "groth16",
+            "curve": "bn128",
+            "nPublic": 1,
+            "vk_alpha_1": g1(&vk.alpha_g1),
+            "vk_beta_2": g2(&vk.beta_g2),
+            "vk_gamma_2": g2(&vk.gamma_g2),
+            "vk_delta_2": g2(&vk.delta_g2),
+            "IC": vk.gamma_abc_g1.iter().map(g1).collect::<Vec<_>>(),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn insecure_dev_key_from_snarkjs_json() {
+        let json = snarkjs_json(&get_ark_verifying_key());
+        let key = Bitvm2VerifyingKey::insecure_dev_from_snarkjs_json(&json).unwrap();
+        assert!(key.is_insecure());
+        assert_eq!(key.ark_key(), get_ark_verifying_key());
+        assert_eq!(
+            key.verifier_parameters_digest(),
+            verifier_parameters_digest()
+        );
+
+        let json = json.replace("\"nPublic\":1", "\"nPublic\":2");
+        assert!(Bitvm2VerifyingKey::insecure_dev_from_snarkjs_json(&json).is_err());
+    }
+
+    #[test]
+    fn verifying_key_module_is_up_to_date() {
+        const VERIFICATION_KEY_JSON: &str = include_str!("../verification_key.json");
+        assert!(
+            crate::codegen::verifying_key_module(VERIFICATION_KEY_JSON).unwrap()
+                == include_str!("verify/verifying_key.rs"),
+            "src/verify/verifying_key.rs is out of date, run `cargo xtask codegen-vk`"
+        );
+        assert_eq!(
+            ark_key_from_snarkjs_json(VERIFICATION_KEY_JSON).unwrap(),
+            get_ark_verifying_key()
+        );
+    }
 }
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use xshell::{Shell, cmd};
+
+/// snarkjs verifying key the generated module is rendered from, relative to the workspace root.
+const VK_JSON: &str = "shrink_bitvm2/verification_key.json";
+const VK_MODULE: &str = "shrink_bitvm2/src/verify/verifying_key.rs";
+
+/// Regenerates the verifying key constants of `shrink_bitvm2` from a snarkjs verifying key or a
+/// zkey. Without a source, the checked-in `verification_key.json` is used.
+pub struct CodegenVk {
+    source: Option<Source>,
+    /// Only check that the checked-in files are up to date.
+    check: bool,
+}
+
+enum Source {
+    Vk(PathBuf),
+    /// Requires `snarkjs` to export the verifying key.
+    Zkey(PathBuf),
+}
+
+impl CodegenVk {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut codegen = Self {
+            source: None,
+            check: false,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vk" => {
+                    let path = args.next().context("--vk requires a file")?;
+                    codegen.source = Some(Source::Vk(std::path::absolute(path)?));
+                }
+                "--zkey" => {
+                    let path = args.next().context("--zkey requires a file")?;
+                    codegen.source = Some(Source::Zkey(std::path::absolute(path)?));
+                }
+                "--check" => codegen.check = true,
+                _ => bail!("unknown codegen-vk argument: {arg}"),
+            }
+        }
+        Ok(codegen)
+    }
+
+    pub fn run(&self) -> Result<()> {
+        let sh = Shell::new()?;
+        sh.change_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join(".."));
+
+        let vk_json = match &self.source {
+            None => sh.read_file(VK_JSON)?,
+            Some(Source::Vk(path)) => sh.read_file(path)?,
+            Some(Source::Zkey(zkey)) => {
+                let tmp_dir = sh.create_temp_dir()?;
+                let vk_path = tmp_dir.path().join("verification_key.json");
+                cmd!(sh, "snarkjs zkey export verificationkey {zkey} {vk_path}").run()?;
+                sh.read_file(vk_path)?
+            }
+        };
+        let module = shrink_bitvm2::codegen::verifying_key_module(&vk_json)?;
+
+        if self.check {
+            let up_to_date = sh.read_file(VK_JSON)? == vk_json
+                && sh.read_file(VK_MODULE).unwrap_or_default() == module;
+            if !up_to_date {
+                bail!("{VK_MODULE} is out of date, run `cargo xtask codegen-vk`");
+            }
+            println!("{VK_MODULE} is up to date");
+        } else {
+            sh.write_file(VK_JSON, &vk_json)?;
+            sh.write_file(VK_MODULE, &module)?;
+            println!("wrote {VK_JSON} and {VK_MODULE}");
+        }
+        Ok(())
+    }
+}
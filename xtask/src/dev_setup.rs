@@ -0,0 +1,119 @@
+use anyhow::{Context, Result, bail};
+use shrink_bitvm2::setup::{ALLOW_INSECURE_DEV_ENV, DEV_VK_FILE, ZKEY_FILE};
+use std::path::{Path, PathBuf};
+use xshell::{Shell, cmd};
+
+/// Working directory of the development setup, relative to the setup directory.
+const DEV_DIR: &str = "dev-setup";
+
+/// Fixed entropy, the development setup is reproducible and its toxic waste is public.
+const INSECURE_ENTROPY: &str = "shrink-bitvm2 INSECURE development setup";
+
+/// INSECURE single-party Groth16 setup of `verify_for_guest.circom`, for testing the pipeline
+/// without the production zkey. Requires `circom` and `snarkjs`.
+pub struct DevSetup {
+    /// Powers of tau size, as a power of two. Must be at least log2 of the constraint count.
+    power: u32,
+    /// Existing phase 2 prepared powers of tau file to use instead of generating one.
+    ptau: Option<PathBuf>,
+}
+
+impl DevSetup {
+    /// Parses the `dev-setup` specific arguments, returning the others.
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<(Self, Vec<String>)> {
+        let mut dev_setup = Self {
+            power: 24,
+            ptau: None,
+        };
+        let mut rest = Vec::new();
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--power" => {
+                    let power = args.next().context("--power requires a value")?;
+                    dev_setup.power = power.parse().context("invalid --power")?;
+                }
+                "--ptau" => {
+                    let ptau = args.next().context("--ptau requires a file")?;
+                    dev_setup.ptau = Some(std::path::absolute(ptau)?);
+                }
+                _ => rest.push(arg),
+            }
+        }
+        Ok((dev_setup, rest))
+    }
+
+    /// Fingerprint of the generated zkey for the setup step markers.
+    pub fn fingerprint(&self) -> String {
+        format!("insecure-dev {}", self.power)
+    }
+
+    pub fn tool_names() -> [&'static str; 2] {
+        ["circom", "snarkjs"]
+    }
+
+    /// Compiles the circuit to R1CS and writes the zkey and its snarkjs verifying key to the
+    /// setup directory.
+    pub fn generate_zkey(&self, sh: &Shell, verify_for_guest_circom: &Path) -> Result<()> {
+        for tool in Self::tool_names() {
+            if cmd!(sh, "which {tool}")
+                .quiet()
+                .ignore_stdout()
+                .run()
+                .is_err()
+            {
+                bail!("dev-setup requires {tool} on the PATH");
+            }
+        }
+        println!("WARNING: generating an INSECURE development zkey");
+        sh.create_dir(DEV_DIR)?;
+
+        // verify_for_guest.circom -> verify_for_guest.r1cs, and the .sym file for
+        // `cargo xtask inspect-witness`
+        cmd!(
+            sh,
+            "circom {verify_for_guest_circom} --r1cs --sym -l . -o {DEV_DIR}"
+        )
+        .run()?;
+        let r1cs = format!("{DEV_DIR}/verify_for_guest.r1cs");
+
+        let ptau = match &self.ptau {
+            Some(ptau) => ptau.clone(),
+            None => {
+                let power = self.power.to_string();
+                let pot_0 = format!("{DEV_DIR}/pot_0.ptau");
+                let pot_1 = format!("{DEV_DIR}/pot_1.ptau");
+                let pot_final = PathBuf::from(format!("{DEV_DIR}/pot_final.ptau"));
+                cmd!(sh, "snarkjs powersoftau new bn128 {power} {pot_0}").run()?;
+                cmd!(
+                    sh,
+                    "snarkjs powersoftau contribute {pot_0} {pot_1} --name=dev -e={INSECURE_ENTROPY}"
+                )
+                .run()?;
+                cmd!(sh, "snarkjs powersoftau prepare phase2 {pot_1} {pot_final}").run()?;
+                pot_final
+            }
+        };
+
+        let zkey_0 = format!("{DEV_DIR}/verify_for_guest_0.zkey");
+        let tmp_zkey = format!("{ZKEY_FILE}.tmp");
+        let tmp_vk = format!("{DEV_VK_FILE}.tmp");
+        cmd!(sh, "snarkjs groth16 setup {r1cs} {ptau} {zkey_0}").run()?;
+        cmd!(
+            sh,
+            "snarkjs zkey contribute {zkey_0} {tmp_zkey} --name=dev -e={INSECURE_ENTROPY}"
+        )
+        .run()?;
+        cmd!(
+            sh,
+            "snarkjs zkey export verificationkey {tmp_zkey} {tmp_vk}"
+        )
+        .run()?;
+
+        let dir = sh.current_dir();
+        std::fs::rename(dir.join(&tmp_vk), dir.join(DEV_VK_FILE))?;
+        std::fs::rename(dir.join(&tmp_zkey), dir.join(ZKEY_FILE))?;
+        println!("set {ALLOW_INSECURE_DEV_ENV}=1 to prove with the INSECURE development zkey");
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{Context, Result, bail};
+use shrink_bitvm2::{Symbols, VerifyForGuestInput, Witness};
+use std::path::{Path, PathBuf};
+
+/// Prints the public output, journal and control root of a `verify_for_guest` witness, and
+/// diagnoses circuit inputs whose witness generation failed.
+pub struct InspectWitness {
+    wtns: Option<PathBuf>,
+    /// circom `.sym` file of the circuit, to look signals up by name.
+    sym: Option<PathBuf>,
+    /// Prefixes of the names of other signals to print.
+    signals: Vec<String>,
+    input: Option<PathBuf>,
+    /// Input of a known good proof to compare `input` with.
+    expected: Option<PathBuf>,
+}
+
+impl InspectWitness {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut inspect = Self {
+            wtns: None,
+            sym: None,
+            signals: Vec::new(),
+            input: None,
+            expected: None,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("{arg} requires a value"))
+            };
+            match arg.as_str() {
+                "--wtns" => inspect.wtns = Some(value()?.into()),
+                "--sym" => inspect.sym = Some(value()?.into()),
+                "--signal" => inspect.signals.push(value()?),
+                "--input" => inspect.input = Some(value()?.into()),
+                "--expected" => inspect.expected = Some(value()?.into()),
+                _ => bail!("unknown inspect-witness argument: {arg}"),
+            }
+        }
+        if inspect.wtns.is_none() && inspect.input.is_none() {
+            bail!(
+                "usage: cargo xtask inspect-witness [--wtns <file>] [--sym <file>] \
+                 [--signal <prefix>]... [--input <file>] [--expected <file>]"
+            );
+        }
+        Ok(inspect)
+    }
+
+    pub fn run(&self) -> Result<()> {
+        if let Some(input) = &self.input {
+            self.inspect_input(input)?;
+        }
+        if let Some(wtns) = &self.wtns {
+            self.inspect_witness(wtns)?;
+        }
+        Ok(())
+    }
+
+    fn inspect_input(&self, path: &Path) -> Result<()> {
+        let input = read_input(path)?;
+        match input.check() {
+            Ok(()) => println!("input: well formed"),
+            Err(err) => println!("input: {err}"),
+        }
+        if let Some(expected) = &self.expected {
+            let diff = input.diff(&read_input(expected)?);
+            if diff.is_empty() {
+                println!("input: same as {}", expected.display());
+            } else {
+                println!(
+                    "input: differs from {} in {}",
+                    expected.display(),
+                    diff.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn inspect_witness(&self, path: &Path) -> Result<()> {
+        let witness_encoded =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let witness = Witness::decode(&witness_encoded)?;
+        println!("signals: {}", witness.len());
+        println!("public output: {:#x}", witness.public_output()?);
+
+        let Some(sym) = &self.sym else {
+            if !self.signals.is_empty() {
+                bail!("--signal requires --sym");
+            }
+            return Ok(());
+        };
+        let symbols = Symbols::read(sym)?;
+
+        let journal_bits = (0..256)
+            .map(|i| witness.named(&symbols, &format!("main.journal_digest_bits[{i}]")))
+            .collect::<Result<Vec<_>>>()?;
+        let journal: Vec<u8> = journal_bits
+            .chunks(8)
+            .map(|bits| {
+                bits.iter()
+                    .fold(0u8, |byte, bit| (byte << 1) | u8::from(bit.bits() == 1))
+            })
+            .collect();
+        println!("journal: {}", hex(&journal));
+        for i in 0..2 {
+            let limb = witness.named(&symbols, &format!("main.control_root[{i}]"))?;
+            println!("control root[{i}]: {limb:#x}");
+        }
+
+        for prefix in &self.signals {
+            let mut found = false;
+            for (name, index) in symbols.matching(prefix) {
+                found = true;
+                match witness.signal(index) {
+                    Some(value) => println!("{name} = {value}"),
+                    None => println!("{name}: index {index} is out of the witness"),
+                }
+            }
+            if !found {
+                println!("{prefix}: no matching signal");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_input(path: &Path) -> Result<VerifyForGuestInput> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    VerifyForGuestInput::from_json(&json)
+        .with_context(|| format!("invalid circuit input {}", path.display()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -1,7 +1,29 @@
+mod codegen_vk;
+#[cfg(feature = "setup-groth16")]
+mod dev_setup;
+mod inspect_witness;
 #[cfg(feature = "setup-groth16")]
 mod setup_groth16;
+mod status;
+
+use anyhow::{Result, bail};
 
-fn main() {
-    #[cfg(feature = "setup-groth16")]
-    setup_groth16::SetupGroth16::run();
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        #[cfg(feature = "setup-groth16")]
+        Some("setup-groth16") => setup_groth16::SetupGroth16::parse(args)?.run(),
+        #[cfg(feature = "setup-groth16")]
+        Some("dev-setup") => {
+            let (dev_setup, args) = dev_setup::DevSetup::parse(args)?;
+            setup_groth16::SetupGroth16::parse(args.into_iter())?
+                .with_dev_setup(dev_setup)
+                .run()
+        }
+        Some("codegen-vk") => codegen_vk::CodegenVk::parse(args)?.run(),
+        Some("inspect-witness") => inspect_witness::InspectWitness::parse(args)?.run(),
+        Some("status") => status::Status::parse(args)?.run(),
+        Some(command) => bail!("unknown command: {command}"),
+        None => bail!("usage: cargo xtask <command>"),
+    }
 }
@@ -12,21 +12,50 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use anyhow::{Context, Result, bail, ensure};
 use risc0_groth16_sys::{SetupParams, WitnessParams};
 use rlimit::{INFINITY, Resource, setrlimit};
-use std::path::Path;
+use shrink_bitvm2::{
+    bundle::CIRCUIT_COMMIT,
+    setup::{
+        DEV_VK_FILE, GRAPH_FILE, MANIFEST_VERSION, SetupDir, SetupManifest, ZKEY_FILE, sha256_file,
+    },
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 use xshell::{Shell, cmd};
 
+use crate::dev_setup::DevSetup;
+
 const CIRCOM_WITNESSCALC_URL: &str = "https://github.com/iden3/circom-witnesscalc.git";
 const CIRCOM_WITNESSCALC_COMMIT: &str = "b7ff0ffd9c72c8f60896ce131ee98a35aba96009"; // 0.2.1
 
 const RISC0_TO_BITVM2_URL: &str = "https://github.com/ec2/risc0-to-bitvm2-boundless.git";
-const RISC0_TO_BITVM2_COMMIT: &str = "b47483ae7ff2bbfbf6d4448bac76aa26afc80e47";
+const RISC0_TO_BITVM2_COMMIT: &str = CIRCUIT_COMMIT;
 
 const CIRCOMLIB_URL: &str = "https://github.com/iden3/circomlib.git";
 const CIRCOMLIB_COMMIT: &str = "35e54ea21da3e8762557234298dbb553c175ea8d";
 
-pub struct SetupGroth16;
+const ZKEY_URL: &str = "https://static.testnet.citrea.xyz/conf/verify_for_guest_final.zkey";
+
+/// Outputs of `risc0_groth16_sys::setup`, recorded in the manifest when present.
+const PREPROCESSED_FILES: &[&str] = &["fuzzed_msm_results.bin", "preprocessed_coeffs.bin"];
+
+/// Pinned SHA-256 checksums of the downloaded files, in `sha256sum` format. Git repositories are
+/// pinned by commit instead, and git LFS checks the SHA-256 of the LFS objects it fetches.
+const CHECKSUMS: &str = include_str!("../checksums.sha256");
+
+pub struct SetupGroth16 {
+    /// Install from a pre-populated directory instead of the network. It must contain the zkey
+    /// and checkouts of the `risc0-to-bitvm2`, `circomlib` and `circom-witnesscalc` repositories
+    /// (with their LFS files), and the `build-circuit` dependencies must be in the cargo cache.
+    from_dir: Option<PathBuf>,
+    /// Generate an INSECURE development zkey instead of downloading the production one.
+    dev_setup: Option<DevSetup>,
+}
+
 const CIRCUIT_FILES: &[&str] = &[
     "blake3_common.circom",
     "blake3_compression.circom",
@@ -35,109 +64,391 @@ const CIRCUIT_FILES: &[&str] = &[
     "verify_for_guest.circom",
 ];
 
-fn download_circuits(sh: &Shell) {
-    if !sh.path_exists("risc0-to-bitvm2") {
-        cmd!(sh, "git clone {RISC0_TO_BITVM2_URL} risc0-to-bitvm2")
-            .run()
-            .unwrap();
+fn pinned_sha256(name: &str) -> Option<&'static str> {
+    CHECKSUMS
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once("  "))
+        .find(|(_, file)| *file == name)
+        .map(|(sha256, _)| sha256)
+}
+
+/// Checks `path` against the pinned checksum of `name`.
+fn verify_checksum(path: &Path, name: &str) -> Result<()> {
+    let actual = sha256_file(path)?;
+    let Some(expected) = pinned_sha256(name) else {
+        bail!(
+            "no pinned SHA-256 for {name} in xtask/checksums.sha256, {} has SHA-256 {actual}",
+            path.display()
+        );
+    };
+    ensure!(
+        actual == expected,
+        "SHA-256 mismatch for {}: expected {expected}, got {actual}",
+        path.display()
+    );
+    println!("verified {name}: {actual}");
+    Ok(())
+}
+
+/// First line of `<tool> --version`.
+fn tool_version(sh: &Shell, tool: &str) -> Result<String> {
+    let version = cmd!(sh, "{tool} --version").ignore_status().read()?;
+    Ok(version.lines().next().unwrap_or_default().to_string())
+}
+
+fn write_manifest(sh: &Shell, setup_dir: &Path, insecure_dev: bool) -> Result<()> {
+    let setup_dir = SetupDir::new(setup_dir);
+    let mut artifacts = vec![
+        setup_dir.artifact(ZKEY_FILE)?,
+        setup_dir.artifact(GRAPH_FILE)?,
+    ];
+    if insecure_dev {
+        artifacts.push(setup_dir.artifact(DEV_VK_FILE)?);
     }
-    {
-        let _cd = sh.push_dir("risc0-to-bitvm2");
-        cmd!(sh, "git checkout {RISC0_TO_BITVM2_COMMIT}")
-            .run()
-            .unwrap();
-        cmd!(sh, "git lfs pull").run().unwrap();
+    let mut preprocessed = true;
+    for file in PREPROCESSED_FILES {
+        if setup_dir.path().join(file).exists() {
+            artifacts.push(setup_dir.artifact(file)?);
+        } else {
+            println!("WARNING: {file} is missing, preprocessing did not complete");
+            preprocessed = false;
+        }
     }
-    sh.create_dir("groth16_proof").unwrap();
-    sh.create_dir("groth16_proof/circuits").unwrap();
-    // Copy circuit files every time because we modify stark_verify.circom
-    for file in CIRCUIT_FILES {
-        let src = format!("risc0-to-bitvm2/groth16_proof/circuits/{file}");
-        let dst = format!("groth16_proof/circuits/{file}");
-        sh.copy_file(&src, &dst).unwrap();
+
+    let mut manifest = SetupManifest {
+        version: MANIFEST_VERSION,
+        circuit_commit: CIRCUIT_COMMIT.to_string(),
+        sources: BTreeMap::from([
+            (
+                "risc0-to-bitvm2".to_string(),
+                RISC0_TO_BITVM2_COMMIT.to_string(),
+            ),
+            ("circomlib".to_string(), CIRCOMLIB_COMMIT.to_string()),
+            (
+                "circom-witnesscalc".to_string(),
+                CIRCOM_WITNESSCALC_COMMIT.to_string(),
+            ),
+        ]),
+        tools: BTreeMap::from([
+            ("git".to_string(), tool_version(sh, "git")?),
+            ("cargo".to_string(), tool_version(sh, "cargo")?),
+            (
+                "build-circuit".to_string(),
+                format!("circom-witnesscalc {CIRCOM_WITNESSCALC_COMMIT}"),
+            ),
+        ]),
+        artifacts,
+        preprocessed,
+        insecure_dev,
+    };
+    if insecure_dev {
+        for tool in DevSetup::tool_names() {
+            manifest
+                .tools
+                .insert(tool.to_string(), tool_version(sh, tool)?);
+        }
     }
-    // Delete the last line of stark_verify.circom so that we only use its template
-    cmd!(
-        sh,
-        "sed -i '$d' ./groth16_proof/circuits/stark_verify.circom"
-    )
-    .run()
-    .unwrap();
+    setup_dir.write_manifest(&manifest)?;
+    println!("wrote {}", setup_dir.manifest_path().display());
+    Ok(())
 }
 
-impl SetupGroth16 {
-    pub fn run() {
-        setrlimit(Resource::STACK, INFINITY, INFINITY).unwrap();
+/// Checks that the working tree of the checkout at `dir` is exactly its HEAD commit, which pins
+/// its contents. Used for checkouts copied by `--from-dir`, which git didn't fetch itself.
+fn verify_worktree(sh: &Shell, dir: &str) -> Result<()> {
+    let _cd = sh.push_dir(dir);
+    // Rehash every file instead of trusting the stat data of the copied index
+    cmd!(sh, "git update-index -q --really-refresh")
+        .ignore_status()
+        .run()?;
+    let changes = cmd!(sh, "git status --porcelain --untracked-files=no").read()?;
+    ensure!(
+        changes.trim().is_empty(),
+        "{dir} differs from its pinned commit:\n{changes}"
+    );
+    Ok(())
+}
 
-        let sh = Shell::new().unwrap();
-        let setup_dir = std::env::var("RISC0_BVM2_SETUP_DIR");
-        let setup_dir = setup_dir
-            .as_ref()
-            .map(Path::new)
-            .expect("must provide RISC0_BVM2_SETUP_DIR");
-        println!("RISC0_BVM2_SETUP_DIR: {}", setup_dir.display());
+/// Checks the LFS files of the checkout at `dir` against the SHA-256 in their pointers, which
+/// the pinned commit contains. `git lfs pull` does this for fetched files, this is for
+/// checkouts copied by `--from-dir`.
+fn verify_lfs_files(sh: &Shell, dir: &str) -> Result<()> {
+    let _cd = sh.push_dir(dir);
+    let files = cmd!(sh, "git lfs ls-files --long").read()?;
+    for line in files.lines() {
+        // <oid> <*|-> <path>
+        let mut fields = line.splitn(3, ' ');
+        let (Some(oid), Some(_), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            bail!("unexpected `git lfs ls-files` output: {line}");
+        };
+        let actual = sha256_file(&sh.current_dir().join(path))?;
+        ensure!(
+            actual == oid,
+            "LFS file {dir}/{path} has SHA-256 {actual}, expected {oid}, run `git lfs pull` in \
+             the source checkout"
+        );
+    }
+    println!("verified LFS files of {dir}");
+    Ok(())
+}
+
+/// Checks out `commit` in the repository at `dir`, and checks that it is what HEAD resolves to.
+fn checkout(sh: &Shell, dir: &str, commit: &str) -> Result<()> {
+    let _cd = sh.push_dir(dir);
+    cmd!(sh, "git checkout --quiet {commit}").run()?;
+    let head = cmd!(sh, "git rev-parse HEAD").read()?;
+    ensure!(
+        head.trim() == commit,
+        "{dir} is at {head}, expected {commit}"
+    );
+    Ok(())
+}
 
-        sh.change_dir(sh.create_dir(setup_dir).unwrap());
+/// Directory of the per-step completion markers, relative to the setup directory.
+const STEPS_DIR: &str = ".setup-steps";
 
-        download_circuits(&sh);
+/// Runs `f` unless the step already completed with the same `fingerprint`, then records its
+/// completion. The fingerprint covers the pinned inputs of the step, so changing a pin reruns it.
+fn step(sh: &Shell, name: &str, fingerprint: &str, f: impl FnOnce() -> Result<()>) -> Result<()> {
+    let marker = Path::new(STEPS_DIR).join(format!("{name}.done"));
+    if sh.path_exists(&marker) && sh.read_file(&marker)? == fingerprint {
+        println!("step {name}: already done");
+        return Ok(());
+    }
+    println!("step {name}: running");
+    f().with_context(|| format!("setup step {name} failed"))?;
 
-        let mut setup_params = SetupParams::new(setup_dir).unwrap();
-        setup_params.srs_path = setup_dir
-            .join("verify_for_guest_final.zkey")
-            .try_into()
-            .unwrap();
+    sh.create_dir(STEPS_DIR)?;
+    let tmp_marker = marker.with_extension("tmp");
+    sh.write_file(&tmp_marker, fingerprint)?;
+    std::fs::rename(
+        sh.current_dir().join(&tmp_marker),
+        sh.current_dir().join(&marker),
+    )?;
+    Ok(())
+}
 
-        let mut witness_params = WitnessParams::new(setup_dir);
-        witness_params.graph_path = setup_dir.join("verify_for_guest_graph.bin");
+/// Moves `tmp_path` to `path` once the step producing it succeeded, so an interrupted step never
+/// leaves a partial output at `path`.
+fn commit_output(sh: &Shell, tmp_path: &Path, path: &Path) -> Result<()> {
+    std::fs::rename(sh.current_dir().join(tmp_path), sh.current_dir().join(path)).with_context(
+        || {
+            format!(
+                "failed to move {} to {}",
+                tmp_path.display(),
+                path.display()
+            )
+        },
+    )
+}
 
-        if !sh.path_exists(setup_params.srs_path.as_path()) {
-            let zkey_path = setup_params.srs_path.as_path().to_path_buf();
-            let url = "https://static.testnet.citrea.xyz/conf/verify_for_guest_final.zkey";
-            cmd!(sh, "curl -o {zkey_path} {url}").run().unwrap();
+impl SetupGroth16 {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut from_dir = None;
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--from-dir" => {
+                    let dir = args.next().context("--from-dir requires a directory")?;
+                    from_dir = Some(std::path::absolute(dir)?);
+                }
+                _ => bail!("unknown setup-groth16 argument: {arg}"),
+            }
         }
+        Ok(Self {
+            from_dir,
+            dev_setup: None,
+        })
+    }
 
-        if !sh.path_exists("circom-witnesscalc") {
-            cmd!(sh, "git clone {CIRCOM_WITNESSCALC_URL}")
-                .run()
-                .unwrap();
+    pub fn with_dev_setup(self, dev_setup: DevSetup) -> Self {
+        Self {
+            dev_setup: Some(dev_setup),
+            ..self
         }
+    }
 
-        if !sh.path_exists("circomlib") {
-            cmd!(sh, "git clone {CIRCOMLIB_URL}").run().unwrap();
+    /// Clones `name` from `url`, or copies it from the `--from-dir` directory, and checks out
+    /// `commit`. An existing checkout is reused if `commit` can be checked out in it.
+    fn fetch_repo(&self, sh: &Shell, url: &str, name: &str, commit: &str) -> Result<()> {
+        if sh.path_exists(name) {
+            match checkout(sh, name, commit) {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    println!("removing incomplete checkout {name}: {err:#}");
+                    sh.remove_path(name)?;
+                }
+            }
         }
-        {
-            let _cd = sh.push_dir("circomlib");
-            cmd!(sh, "git checkout {CIRCOMLIB_COMMIT}").run().unwrap();
+
+        let tmp_name = format!("{name}.tmp");
+        sh.remove_path(&tmp_name)?;
+        match &self.from_dir {
+            Some(from_dir) => {
+                let src = from_dir.join(name);
+                ensure!(src.is_dir(), "{} not found", src.display());
+                cmd!(sh, "cp -a {src} {tmp_name}").run()?;
+            }
+            None => cmd!(sh, "git clone {url} {tmp_name}").run()?,
         }
+        checkout(sh, &tmp_name, commit)?;
+        if self.from_dir.is_some() {
+            verify_worktree(sh, &tmp_name)?;
+        }
+        commit_output(sh, Path::new(&tmp_name), Path::new(name))
+    }
 
-        let build_circuit = "circom-witnesscalc/target/release/build-circuit";
-        if !sh.path_exists(build_circuit) {
-            let _cd = sh.push_dir("circom-witnesscalc");
-            cmd!(sh, "git checkout {CIRCOM_WITNESSCALC_COMMIT}")
-                .run()
-                .unwrap();
-            cmd!(sh, "cargo build --release -p build-circuit")
-                .run()
-                .unwrap();
+    fn fetch_zkey(&self, sh: &Shell) -> Result<()> {
+        let zkey_path = Path::new(ZKEY_FILE);
+        if sh.path_exists(zkey_path) {
+            match verify_checksum(&sh.current_dir().join(zkey_path), ZKEY_FILE) {
+                Ok(()) => return Ok(()),
+                // e.g. a development zkey
+                Err(err) => println!("replacing {ZKEY_FILE}: {err:#}"),
+            }
+        }
+        sh.remove_path(DEV_VK_FILE)?;
+
+        // Verify before moving into place, so a bad download is never used
+        let tmp_path = PathBuf::from(format!("{ZKEY_FILE}.tmp"));
+        match &self.from_dir {
+            Some(from_dir) => sh.copy_file(from_dir.join(ZKEY_FILE), &tmp_path)?,
+            None => cmd!(sh, "curl --fail --location -o {tmp_path} {ZKEY_URL}").run()?,
+        }
+        if let Err(err) = verify_checksum(&sh.current_dir().join(&tmp_path), ZKEY_FILE) {
+            sh.remove_path(&tmp_path)?;
+            return Err(err);
+        }
+        commit_output(sh, &tmp_path, zkey_path)
+    }
+
+    fn fetch_circuits(&self, sh: &Shell) -> Result<()> {
+        self.fetch_repo(
+            sh,
+            RISC0_TO_BITVM2_URL,
+            "risc0-to-bitvm2",
+            RISC0_TO_BITVM2_COMMIT,
+        )?;
+        match self.from_dir {
+            Some(_) => verify_lfs_files(sh, "risc0-to-bitvm2")?,
+            None => {
+                let _cd = sh.push_dir("risc0-to-bitvm2");
+                cmd!(sh, "git lfs pull").run()?;
+            }
         }
+        Ok(())
+    }
+
+    fn copy_circuits(&self, sh: &Shell) -> Result<()> {
+        let tmp_dir = "groth16_proof/circuits.tmp";
+        sh.remove_path(tmp_dir)?;
+        sh.create_dir(tmp_dir)?;
+        for file in CIRCUIT_FILES {
+            let src = format!("risc0-to-bitvm2/groth16_proof/circuits/{file}");
+            sh.copy_file(&src, format!("{tmp_dir}/{file}"))?;
+        }
+        // Delete the last line of stark_verify.circom so that we only use its template
+        let stark_verify = format!("{tmp_dir}/stark_verify.circom");
+        cmd!(sh, "sed -i '$d' {stark_verify}").run()?;
+
+        sh.remove_path("groth16_proof/circuits")?;
+        commit_output(sh, Path::new(tmp_dir), Path::new("groth16_proof/circuits"))
+    }
+
+    pub fn run(&self) -> Result<()> {
+        setrlimit(Resource::STACK, INFINITY, INFINITY)?;
+
+        let sh = Shell::new()?;
+        let setup_dir = std::env::var("RISC0_BVM2_SETUP_DIR");
+        let setup_dir = setup_dir
+            .as_ref()
+            .map(Path::new)
+            .context("must provide RISC0_BVM2_SETUP_DIR")?;
+        println!("RISC0_BVM2_SETUP_DIR: {}", setup_dir.display());
+        if let Some(from_dir) = &self.from_dir {
+            println!("installing from {}", from_dir.display());
+        }
+
+        sh.change_dir(sh.create_dir(setup_dir)?);
+
+        step(&sh, "risc0-to-bitvm2", RISC0_TO_BITVM2_COMMIT, || {
+            self.fetch_circuits(&sh)
+        })?;
+        step(&sh, "circuits", RISC0_TO_BITVM2_COMMIT, || {
+            self.copy_circuits(&sh)
+        })?;
+
+        let mut setup_params = SetupParams::new(setup_dir)?;
+        setup_params.srs_path = setup_dir.join(ZKEY_FILE).try_into()?;
+
+        let mut witness_params = WitnessParams::new(setup_dir);
+        witness_params.graph_path = setup_dir.join(GRAPH_FILE);
+
+        step(&sh, "circomlib", CIRCOMLIB_COMMIT, || {
+            self.fetch_repo(&sh, CIRCOMLIB_URL, "circomlib", CIRCOMLIB_COMMIT)
+        })?;
+        step(&sh, "circom-witnesscalc", CIRCOM_WITNESSCALC_COMMIT, || {
+            self.fetch_repo(
+                &sh,
+                CIRCOM_WITNESSCALC_URL,
+                "circom-witnesscalc",
+                CIRCOM_WITNESSCALC_COMMIT,
+            )
+        })?;
 
         let stark_verify_circom = setup_dir.join("groth16_proof/circuits/verify_for_guest.circom");
         if !sh.path_exists(&stark_verify_circom) {
-            panic!(
+            bail!(
                 "Run from top of workspace. Could not find: {}",
                 stark_verify_circom.display()
             );
         }
 
-        // // verify_for_guest.circom -> verify_for_guest_graph.bin
-        let graph_path = &witness_params.graph_path;
-        if !sh.path_exists(graph_path) {
-            cmd!(sh, "{build_circuit} {stark_verify_circom} {graph_path}")
-                .run()
-                .unwrap();
-        }
+        let zkey_fingerprint = match &self.dev_setup {
+            Some(dev_setup) => {
+                let fingerprint = format!("{} {RISC0_TO_BITVM2_COMMIT}", dev_setup.fingerprint());
+                step(&sh, "zkey", &fingerprint, || {
+                    dev_setup.generate_zkey(&sh, &stark_verify_circom)
+                })?;
+                fingerprint
+            }
+            None => {
+                let fingerprint = pinned_sha256(ZKEY_FILE)
+                    .with_context(|| {
+                        format!("no pinned SHA-256 for {ZKEY_FILE} in xtask/checksums.sha256")
+                    })?
+                    .to_string();
+                step(&sh, "zkey", &fingerprint, || self.fetch_zkey(&sh))?;
+                fingerprint
+            }
+        };
+
+        let build_circuit = "circom-witnesscalc/target/release/build-circuit";
+        step(&sh, "build-circuit", CIRCOM_WITNESSCALC_COMMIT, || {
+            let _cd = sh.push_dir("circom-witnesscalc");
+            let offline = self.from_dir.as_ref().map(|_| "--offline");
+            cmd!(sh, "cargo build --release {offline...} -p build-circuit").run()?;
+            Ok(())
+        })?;
+
+        // verify_for_guest.circom -> verify_for_guest_graph.bin
+        let graph_fingerprint = format!("{RISC0_TO_BITVM2_COMMIT} {CIRCOM_WITNESSCALC_COMMIT}");
+        step(&sh, "graph", &graph_fingerprint, || {
+            let graph_path = &witness_params.graph_path;
+            let tmp_graph_path = graph_path.with_extension("bin.tmp");
+            cmd!(sh, "{build_circuit} {stark_verify_circom} {tmp_graph_path}").run()?;
+            commit_output(&sh, &tmp_graph_path, graph_path)
+        })?;
 
         // stark_verify_final.zkey -> (fuzzed_msm_results.bin, preprocessed_coeffs.bin)
-        risc0_groth16_sys::setup(&setup_params).unwrap();
+        step(&sh, "preprocess", &zkey_fingerprint, || {
+            risc0_groth16_sys::setup(&setup_params)?;
+            Ok(())
+        })?;
+
+        write_manifest(&sh, setup_dir, self.dev_setup.is_some())?;
+        SetupDir::new(setup_dir).validate()?;
+        Ok(())
     }
 }
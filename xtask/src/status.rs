@@ -0,0 +1,50 @@
+use anyhow::{Result, bail};
+use shrink_bitvm2::setup::SetupDir;
+
+/// Prints the setup manifest of `RISC0_BVM2_SETUP_DIR` and checks the setup against it.
+pub struct Status {
+    /// Also check the SHA-256 of every artifact, which reads the whole zkey.
+    hashes: bool,
+}
+
+impl Status {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut hashes = false;
+        for arg in args {
+            match arg.as_str() {
+                "--hashes" => hashes = true,
+                _ => bail!("unknown status argument: {arg}"),
+            }
+        }
+        Ok(Self { hashes })
+    }
+
+    pub fn run(&self) -> Result<()> {
+        let setup_dir = SetupDir::from_env()?;
+        println!("RISC0_BVM2_SETUP_DIR: {}", setup_dir.path().display());
+
+        let manifest = setup_dir.read_manifest()?;
+        println!("circuit commit: {}", manifest.circuit_commit);
+        println!("preprocessed: {}", manifest.preprocessed);
+        for (source, commit) in &manifest.sources {
+            println!("source {source}: {commit}");
+        }
+        for (tool, version) in &manifest.tools {
+            println!("tool {tool}: {version}");
+        }
+        for artifact in &manifest.artifacts {
+            println!(
+                "artifact {}: {} bytes, sha256 {}",
+                artifact.path, artifact.size, artifact.sha256
+            );
+        }
+
+        if self.hashes {
+            setup_dir.verify_hashes()?;
+        } else {
+            setup_dir.validate()?;
+        }
+        println!("setup is valid");
+        Ok(())
+    }
+}